@@ -0,0 +1,249 @@
+//! Derive macros used inside `mc_headless_auth`, the generated code refers to its private modules
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    Data, DeriveInput, Error, Expr, Field, Fields, GenericArgument, Ident, PathArguments, Type,
+    parse_macro_input,
+};
+
+/// Derives `VersionedPacket`, `ReadPacketData` and `WritePacketData` for a packet struct
+///
+/// Fields are read and written in the order they are declared.  
+/// `ReadPacketData` uses the newest protocol version, `WritePacketData` is only derived  
+/// for packets without gated fields, since writing those fails if a field is missing.  
+///
+/// ## Attributes
+/// - `#[packet(id = 0x00, state = Login)]` on the struct, `state` is a `State` variant
+/// - `#[packet(since = protocol_version::V1_20_2, until = ...)]` on a field only sent  
+///   from `since` and before `until`, either can be left out. The field has to be an `Option`,  
+///   which is `None` for other versions. Writing returns `TypeError::MissingField` if it is `None`  
+///   for a version that has it.
+///
+/// ## Example
+/// ```ignore
+/// #[derive(Debug, Clone, Packet)]
+/// #[packet(id = 0x00, state = Login)]
+/// pub struct LoginStart {
+///     pub name: PacketString<16>,
+///     #[packet(since = protocol_version::V1_20_2)]
+///     pub uuid: Option<Uuid>,
+/// }
+/// ```
+#[proc_macro_derive(Packet, attributes(packet))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The version range of a field, both ends are protocol version expressions
+#[derive(Default)]
+struct Gate {
+    since: Option<Expr>,
+    until: Option<Expr>,
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+    let (id, state) = packet_attr(&input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unit => Vec::new(),
+            Fields::Unnamed(_) => {
+                return Err(Error::new_spanned(
+                    &input,
+                    "Packet can only be derived for structs with named fields",
+                ));
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input,
+                "Packet can only be derived for structs",
+            ));
+        }
+    };
+
+    let mut reads = Vec::new();
+    let mut writes = Vec::new();
+    let mut names = Vec::new();
+    let mut gated = false;
+
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named fields have idents");
+        let gate = field_gate(field)?;
+        names.push(ident);
+
+        if gate.since.is_none() && gate.until.is_none() {
+            let ty = &field.ty;
+            reads.push(quote! {
+                let #ident = <#ty as crate::minecraft::packet::ReadPacketData>::read(data)?;
+            });
+            writes.push(quote! {
+                crate::minecraft::packet::WritePacketData::write(self.#ident, data);
+            });
+            continue;
+        }
+
+        gated = true;
+        let inner = option_inner(&field.ty).ok_or_else(|| {
+            Error::new_spanned(
+                &field.ty,
+                "fields with `since` or `until` have to be an Option",
+            )
+        })?;
+        let condition = match (gate.since, gate.until) {
+            (Some(since), Some(until)) => {
+                quote! { ((#since)..(#until)).contains(&protocol_version) }
+            }
+            (Some(since), None) => quote! { protocol_version >= (#since) },
+            (None, Some(until)) => quote! { protocol_version < (#until) },
+            (None, None) => unreachable!("ungated fields are handled above"),
+        };
+
+        reads.push(quote! {
+            let #ident = if #condition {
+                Some(<#inner as crate::minecraft::packet::ReadPacketData>::read(data)?)
+            } else {
+                None
+            };
+        });
+        let missing = format!("{name}::{ident}");
+        writes.push(quote! {
+            if #condition {
+                match self.#ident {
+                    Some(value) => crate::minecraft::packet::WritePacketData::write(value, data),
+                    // leaving it out would send a malformed packet
+                    None => {
+                        return Err(crate::error::TypeError::MissingField(#missing, protocol_version));
+                    }
+                }
+            }
+        });
+    }
+
+    // without gated fields writing can't fail, so it works without a version too
+    let (write_versioned, write) = if gated {
+        (quote! { #(#writes)* }, quote! {})
+    } else {
+        (
+            quote! { crate::minecraft::packet::WritePacketData::write(self, data); },
+            quote! {
+                impl crate::minecraft::packet::WritePacketData for #name {
+                    fn write(self, data: &mut ::bytes::BytesMut) {
+                        #(#writes)*
+                    }
+                }
+            },
+        )
+    };
+
+    Ok(quote! {
+        impl crate::minecraft::packet::VersionedPacket for #name {
+            const ID: i32 = #id;
+            const STATE: crate::minecraft::packet::State = crate::minecraft::packet::State::#state;
+
+            // without gated fields the version is unused, without fields the data too
+            #[allow(unused_variables)]
+            fn read_versioned(
+                data: &mut ::bytes::Bytes,
+                protocol_version: i32,
+            ) -> Result<Self, crate::error::TypeError> {
+                #(#reads)*
+
+                Ok(Self { #(#names),* })
+            }
+
+            #[allow(unused_variables)]
+            fn write_versioned(
+                self,
+                data: &mut ::bytes::BytesMut,
+                protocol_version: i32,
+            ) -> Result<(), crate::error::TypeError> {
+                #write_versioned
+
+                Ok(())
+            }
+        }
+
+        impl crate::minecraft::packet::ReadPacketData for #name {
+            fn read(data: &mut ::bytes::Bytes) -> Result<Self, crate::error::TypeError> {
+                <Self as crate::minecraft::packet::VersionedPacket>::read_versioned(data, i32::MAX)
+            }
+        }
+
+        #write
+    })
+}
+
+/// Reads `#[packet(id = ..., state = ...)]` off the struct
+fn packet_attr(input: &DeriveInput) -> Result<(Expr, Ident), Error> {
+    let mut id = None;
+    let mut state = None;
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("packet")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                id = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("state") {
+                state = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `id` or `state`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    match (id, state) {
+        (Some(id), Some(state)) => Ok((id, state)),
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            "missing `#[packet(id = ..., state = ...)]`",
+        )),
+    }
+}
+
+/// Reads `#[packet(since = ..., until = ...)]` off a field
+fn field_gate(field: &Field) -> Result<Gate, Error> {
+    let mut gate = Gate::default();
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("packet")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("since") {
+                gate.since = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("until") {
+                gate.until = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `since` or `until`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(gate)
+}
+
+/// The `T` in `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
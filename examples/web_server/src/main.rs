@@ -42,7 +42,7 @@ fn main() {
                 request.as_reader().read_to_string(&mut user_token).unwrap();
                 println!("  Verifying {}", user_token);
 
-                match mc.verify(user_token) {
+                match mc.verify(user_token, None) {
                     Some(u) => {
                         let json = serde_json::to_string(&json!({
                             "username": u.username,
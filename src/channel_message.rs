@@ -4,7 +4,9 @@ use crate::{ServerError, player::Player};
 
 #[derive(Debug)]
 pub enum MessageData {
-    OnJoin { player: Player, token: String },
+    // boxed to keep `SendError<ChannelMessage>` in errors small
+    OnJoin { player: Box<Player>, token: String },
+    OnExpire { player: Box<Player>, token: String },
     ConnectionError(Box<ServerError>),
     CloseServer,
 }
@@ -1,23 +1,31 @@
-use std::{io::Cursor, time::Duration};
+use std::{collections::HashSet, io::Cursor, path::Path, sync::Arc, time::Duration};
 
-use constcat::concat;
-use image::{ImageFormat, RgbaImage};
-use serde_json::Value;
+use image::{DynamicImage, ImageError, ImageFormat, RgbaImage, imageops::FilterType};
 
 use crate::{
-    message::{Message, MessageGenerator},
-    token::{Token, TokenGenerator},
+    MCHAError,
+    message::{AnyMessageGenerator, Message, MessageGenerator},
+    signed_token::SignedToken,
+    status::{AnyStatusProvider, StatusPlayers, StatusProvider},
+    text::TextComponent,
+    token::{AnyTokenGenerator, Token, TokenGenerator},
 };
 
 pub(crate) const MIN_SUPPORTED_VERSION: &'static str = "1.21.2";
 pub(crate) const DEFAULT_DESC: &'static str = "Join to link your minecraft account";
-pub(crate) const DEFAULT_LEGACY_DESC: &'static str =
-    concat!("Use a client newer than ", MIN_SUPPORTED_VERSION, "+");
+pub(crate) const DEFAULT_BEDROCK_DESC: &str =
+    "Use Minecraft Java Edition to link your account\nJava Edition only";
 pub(crate) const DEFAULT_ICON: &'static [u8] = include_bytes!("../icon.png");
+pub(crate) const DEFAULT_UNBOUND_MESSAGE: &str =
+    "Join using the server address shown on the website";
 
 /// Config for the Minecraft Server
 #[derive(Debug, Clone)]
-pub struct ServerConfig<T: TokenGenerator = Token, M: MessageGenerator = Message> {
+pub struct ServerConfig<
+    T: TokenGenerator = Token,
+    M: MessageGenerator = Message,
+    S: StatusProvider = StatusConfig,
+> {
     /// The port on which to bind the minecraft server on
     ///
     /// Defaults to `25565` which is the default Minecraft port.  
@@ -28,35 +36,293 @@ pub struct ServerConfig<T: TokenGenerator = Token, M: MessageGenerator = Message
     pub token: T,
     /// For how long a token should remain valid until it gets cleared
     ///
-    /// This ttl is also used for the interval in which tokens ttl are checked.  
+    /// Can be overriden per token with [`TokenGenerator::ttl`].  
     ///
     /// Defaults to `5 minutes`
     pub token_ttl: Duration,
+    /// Verifies the tokens of [`Server::verify_signed`](crate::Server::verify_signed)
+    ///
+    /// Set together with the token generator by [`ServerConfig::with_signed_token`].  
+    /// Can differ from the token generator, like a wrapper that signs with the same key.  
+    ///
+    /// Defaults to `None`, `verify_signed` then never returns a player.  
+    pub signed_token: Option<SignedToken>,
     /// Used to generate the message the user sees when kicked from the server
     ///
     /// Defaults to [`Message`], which will display the token in a green small font,  
     /// and then have a little notice about using the token to link your account.  
     pub message: M,
     /// Config for status packets, values for server favicon, description etc.  
-    pub status: StatusConfig,
+    ///
+    /// Defaults to a [`StatusConfig`], any [`StatusProvider`] can be used for a status that changes.  
+    pub status: S,
+    /// Binds tokens to a nonce registered by a web session
+    ///
+    /// See [`NonceBinding`] for how the nonce is transferred.  
+    ///
+    /// Defaults to `None`, tokens are not bound to anything.  
+    pub nonce_binding: Option<NonceBinding>,
+    /// What happens to a players previous tokens when they join again
+    ///
+    /// Defaults to [`ReissuePolicy::KeepAll`]
+    pub reissue: ReissuePolicy,
+    /// Waits for the client to send its locale before disconnecting
+    ///
+    /// The locale is then passed to [`MessageGenerator::create_message`],  
+    /// the default [`Message`] comes with translations for a handful of languages.  
+    ///
+    /// Defaults to `false`
+    pub wait_for_locale: bool,
+    /// Hostnames that get their own token generator, message, status and token namespace
+    ///
+    /// Players joining through any other hostname get the generators of this config.  
+    /// See [`VirtualHost`] for how hostnames are matched.  
+    /// If two virtual hosts have the same hostname, the later one replaces the earlier one,  
+    /// use [`ServerConfig::with_vhost`] to make that explicit.  
+    ///
+    /// Defaults to no virtual hosts.  
+    pub vhosts: Vec<VirtualHost>,
+    /// The UDP port to answer [queries](https://minecraft.wiki/w/Query) on, used by server lists and monitoring tools
+    ///
+    /// The response is created from [`ServerConfig::status`], usually this is the same as [`ServerConfig::port`].  
+    ///
+    /// Defaults to `None`, queries are not answered.  
+    pub query_port: Option<u16>,
+    /// The UDP port to answer Bedrock Edition pings on, usually `19132`
+    ///
+    /// Bedrock players can't join, but the server list then shows [`StatusConfig::bedrock_description`]  
+    /// instead of failing without an explanation.  
+    ///
+    /// Defaults to `None`, Bedrock pings are not answered.  
+    pub bedrock_port: Option<u16>,
+    /// Lets Bedrock Edition players join on [`ServerConfig::bedrock_port`] to get a token
+    ///
+    /// Their login is verified offline against Mojang's public key, so it needs no requests to Xbox Live.  
+    /// The [`Player`](crate::Player) then has their gamertag as `username` and their [`Player::xuid`](crate::Player::xuid).  
+    /// Clients from 1.19.30 and up are supported.  
+    ///
+    /// Defaults to `false`
+    pub bedrock_login: bool,
+}
+
+/// A hostname with its own token generator, message and status
+///
+/// The hostname is either exact like `play.example.com`, or a wildcard like `*.example.com`  
+/// matching any subdomain. An exact match wins over a wildcard and longer wildcards win over shorter ones.  
+///
+/// Tokens issued through a virtual host are in their own namespace and can only be verified  
+/// through [`Server::vhost`](crate::Server::vhost), with the same hostname as configured here.  
+///
+/// Each virtual host can use generators of different types than the main config.  
+///
+/// ## Example
+/// ```no_run
+/// # use mc_headless_auth::{AlphabetToken, Server, ServerConfig, StatusConfig, TextComponent, VirtualHost};
+/// let skyblock = VirtualHost::new("*.skyblock.example.com")
+///     .with_token(AlphabetToken::default())
+///     .with_status(StatusConfig {
+///         description: Some(TextComponent::text("Join to link your Skyblock account")),
+///         ..Default::default()
+///     });
+/// let config = ServerConfig::default().with_vhost(skyblock);
+/// let server = Server::start(config);
+///
+/// let player = server.vhost("*.skyblock.example.com").unwrap().verify("MJMMJSLXHG", None);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VirtualHost {
+    pub hostname: String,
+    pub(crate) token: AnyTokenGenerator,
+    pub(crate) message: AnyMessageGenerator,
+    pub(crate) status: AnyStatusProvider,
+    /// Verifies the signed tokens of this virtual host, see [`ServerConfig::signed_token`]
+    pub(crate) signed_token: Option<SignedToken>,
+}
+
+impl VirtualHost {
+    /// A virtual host with the default [`Token`], [`Message`] and [`StatusConfig`]
+    pub fn new(hostname: impl Into<String>) -> Self {
+        Self {
+            hostname: hostname.into(),
+            token: Arc::new(Token),
+            message: Arc::new(Message),
+            status: Arc::new(StatusConfig::default()),
+            signed_token: None,
+        }
+    }
+
+    /// Replaces the token generator of this virtual host
+    pub fn with_token(mut self, token: impl TokenGenerator) -> Self {
+        self.token = Arc::new(token);
+        self.signed_token = None;
+        self
+    }
+
+    /// Issues [`SignedToken`]s for this virtual host, see [`ServerConfig::with_signed_token`]
+    pub fn with_signed_token(mut self, signed_token: SignedToken) -> Self {
+        self.token = Arc::new(signed_token.clone());
+        self.signed_token = Some(signed_token);
+        self
+    }
+
+    /// Replaces the message generator of this virtual host
+    pub fn with_message(mut self, message: impl MessageGenerator) -> Self {
+        self.message = Arc::new(message);
+        self
+    }
+
+    /// Replaces the status provider of this virtual host
+    pub fn with_status(mut self, status: impl StatusProvider) -> Self {
+        self.status = Arc::new(status);
+        self
+    }
+
+    /// The hostname without a trailing `.` and in lowercase, the way hosts are compared
+    pub(crate) fn normalized_hostname(&self) -> String {
+        self.hostname.trim_end_matches('.').to_lowercase()
+    }
+
+    /// How well the hostname matches a normalized host, `None` if it doesn't match at all
+    pub(crate) fn specificity(&self, host: &str) -> Option<usize> {
+        let hostname = self.normalized_hostname();
+
+        match hostname.strip_prefix('*') {
+            Some(suffix) => host
+                .strip_suffix(suffix)
+                .filter(|sub| !sub.is_empty() && suffix.starts_with('.'))
+                .map(|_| suffix.len()),
+            // exact matches always win over wildcards
+            None => (host == hostname).then_some(usize::MAX),
+        }
+    }
+}
+
+/// Policy for issuing tokens to a player who already has a valid token
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReissuePolicy {
+    /// Every join issues a new token, older tokens stay valid until they expire
+    #[default]
+    KeepAll,
+    /// Every join issues a new token and invalidates older tokens for the same player
+    ReplacePrevious,
+    /// Shows the players still valid token again instead of issuing a new one
+    ReuseExisting,
+}
+
+/// Config for binding tokens to a web session nonce
+///
+/// The web session registers a nonce with [`Server::register_nonce`](crate::Server::register_nonce),  
+/// and the player joins via `<nonce>.<domain>`, which requires a wildcard DNS record for the domain.  
+///
+/// The token the player receives can then only be verified with the same nonce,  
+/// so a token that is relayed or phished is useless in another web session.  
+#[derive(Debug, Clone)]
+pub struct NonceBinding {
+    /// The domain that nonces are prefixed onto, e.g. `auth.example.com`
+    pub domain: String,
+    /// The message shown to players joining without a registered nonce
+    pub unbound_message: TextComponent,
+}
+
+impl NonceBinding {
+    pub fn new(domain: impl Into<String>) -> Self {
+        Self {
+            domain: domain.into(),
+            unbound_message: TextComponent::text(DEFAULT_UNBOUND_MESSAGE),
+        }
+    }
 }
 
 /// Config for status packets
 #[derive(Debug, Clone)]
 pub struct StatusConfig {
-    /// A 64x64 PNG image to be used as the servers favicon.  
+    /// A 64x64 image to be used as the servers favicon.  
+    ///
+    /// Images of other sizes are resized, use [`StatusConfig::favicon_from_path`] to choose how.  
     ///
     /// ## Default Favicon
     /// ![icon.png](https://bimply.lifelike.dev/d/ZyRdaH55Au)
     pub favicon: Option<RgbaImage>,
-    /// A description for the server, must be a valid [text component](https://minecraft.wiki/w/Text_component_format)
-    pub description: Option<Value>,
+    /// A description for the server
+    pub description: Option<TextComponent>,
     /// Description used for legacy pings.  
     ///
     /// This should be used as a warning message, since servers before 1.7 can't join at all.  
     ///
-    /// But we still respond to server list pings to indicate that the server exists.
+    /// But we still respond to server list pings to indicate that the server exists.  
+    /// Can contain `§` formatting codes.  
+    ///
+    /// Defaults to `None`, which uses `description` for legacy pings too.  
     pub legacy_decription: Option<String>,
+    /// The version name shown when the client is incompatible
+    ///
+    /// Defaults to `None`, which shows the minimum supported version like `1.21.2+`.  
+    pub version_name: Option<String>,
+    /// The protocol version advertised to clients
+    ///
+    /// Clients with a different protocol show the server as incompatible, along with the version name.  
+    ///
+    /// Defaults to `None`, which echoes the protocol of supported clients,  
+    /// and advertises the minimum supported protocol to older clients so they are marked as outdated.  
+    pub protocol: Option<i32>,
+    /// Description shown to Bedrock Edition players, see [`ServerConfig::bedrock_port`]
+    ///
+    /// Can contain `§` formatting codes, a second line is shown below the first.  
+    /// Falls back to the legacy description if not set.  
+    pub bedrock_description: Option<String>,
+    /// The player counts and sample
+    ///
+    /// Defaults to `0/0` with no sample, `None` hides the player count.  
+    pub players: Option<StatusPlayers>,
+}
+
+impl StatusConfig {
+    /// The size of a favicon in pixels, in both width and height
+    pub const FAVICON_SIZE: u32 = 64;
+
+    /// Loads the favicon from an image file, see [`StatusConfig::favicon_from_bytes`]
+    pub fn favicon_from_path(
+        self,
+        path: impl AsRef<Path>,
+        filter: FilterType,
+    ) -> Result<Self, MCHAError> {
+        let bytes = std::fs::read(path).map_err(ImageError::IoError)?;
+        self.favicon_from_bytes(&bytes, filter)
+    }
+
+    /// Loads the favicon from an encoded image like a PNG, JPEG or WebP
+    ///
+    /// Images of any other size are resized to 64x64 with `filter`,  
+    /// non-square images are cropped to their center first.  
+    ///
+    /// ## Example
+    /// ```no_run
+    /// # use mc_headless_auth::{StatusConfig, image::imageops::FilterType};
+    /// let status = StatusConfig::default()
+    ///     .favicon_from_path("logo.jpg", FilterType::Lanczos3)
+    ///     .unwrap();
+    /// ```
+    pub fn favicon_from_bytes(
+        mut self,
+        bytes: &[u8],
+        filter: FilterType,
+    ) -> Result<Self, MCHAError> {
+        let image = image::load_from_memory(bytes)?;
+        self.favicon = Some(resize_favicon(image, filter));
+        Ok(self)
+    }
+
+    /// The description shown to unsupported clients, see [`StatusConfig::legacy_decription`]
+    ///
+    /// Rendered with [`TextComponent::to_json`] for 1.7+ clients, [`TextComponent::to_legacy`]  
+    /// for 1.4 to 1.6 pings and [`TextComponent::to_plain`] for beta pings.  
+    pub fn legacy_description(&self) -> TextComponent {
+        match (&self.legacy_decription, &self.description) {
+            (Some(legacy), _) => TextComponent::from_legacy(legacy),
+            (None, Some(description)) => description.clone(),
+            (None, None) => TextComponent::default(),
+        }
+    }
 }
 
 impl Default for ServerConfig {
@@ -65,10 +331,123 @@ impl Default for ServerConfig {
             port: 25565,
             token: Token,
             token_ttl: Duration::from_secs(5 * 60),
+            signed_token: None,
             message: Message,
             status: StatusConfig::default(),
+            nonce_binding: None,
+            reissue: ReissuePolicy::default(),
+            wait_for_locale: false,
+            vhosts: Vec::new(),
+            query_port: None,
+            bedrock_port: None,
+            bedrock_login: false,
+        }
+    }
+}
+
+impl<T: TokenGenerator, M: MessageGenerator, S: StatusProvider> ServerConfig<T, M, S> {
+    /// Replaces the token generator
+    ///
+    /// Virtual hosts keep their own token generators.  
+    ///
+    /// ## Example
+    /// ```no_run
+    /// # use mc_headless_auth::{AlphabetToken, Server, ServerConfig};
+    /// let config = ServerConfig::default().with_token(AlphabetToken::default());
+    /// let server = Server::start(config);
+    /// ```
+    pub fn with_token<T2: TokenGenerator>(self, token: T2) -> ServerConfig<T2, M, S> {
+        ServerConfig {
+            port: self.port,
+            vhosts: self.vhosts,
+            token,
+            token_ttl: self.token_ttl,
+            signed_token: self.signed_token,
+            message: self.message,
+            status: self.status,
+            nonce_binding: self.nonce_binding,
+            reissue: self.reissue,
+            wait_for_locale: self.wait_for_locale,
+            query_port: self.query_port,
+            bedrock_port: self.bedrock_port,
+            bedrock_login: self.bedrock_login,
+        }
+    }
+
+    /// Issues [`SignedToken`]s and verifies them with [`Server::verify_signed`](crate::Server::verify_signed)
+    ///
+    /// ## Example
+    /// ```no_run
+    /// # use mc_headless_auth::{Server, ServerConfig, SignedToken};
+    /// let config = ServerConfig::default().with_signed_token(SignedToken::new(b"a long random key"));
+    /// let server = Server::start(config);
+    /// ```
+    pub fn with_signed_token(self, signed_token: SignedToken) -> ServerConfig<SignedToken, M, S> {
+        let mut config = self.with_token(signed_token.clone());
+        config.signed_token = Some(signed_token);
+        config
+    }
+
+    /// Replaces the message generator, virtual hosts keep their own
+    pub fn with_message<M2: MessageGenerator>(self, message: M2) -> ServerConfig<T, M2, S> {
+        ServerConfig {
+            vhosts: self.vhosts,
+            port: self.port,
+            token: self.token,
+            token_ttl: self.token_ttl,
+            signed_token: self.signed_token,
+            message,
+            status: self.status,
+            nonce_binding: self.nonce_binding,
+            reissue: self.reissue,
+            wait_for_locale: self.wait_for_locale,
+            query_port: self.query_port,
+            bedrock_port: self.bedrock_port,
+            bedrock_login: self.bedrock_login,
+        }
+    }
+
+    /// Replaces the status provider, virtual hosts keep their own, see [`StatusProvider`]
+    pub fn with_status<S2: StatusProvider>(self, status: S2) -> ServerConfig<T, M, S2> {
+        ServerConfig {
+            vhosts: self.vhosts,
+            port: self.port,
+            token: self.token,
+            token_ttl: self.token_ttl,
+            signed_token: self.signed_token,
+            message: self.message,
+            status,
+            nonce_binding: self.nonce_binding,
+            reissue: self.reissue,
+            wait_for_locale: self.wait_for_locale,
+            query_port: self.query_port,
+            bedrock_port: self.bedrock_port,
+            bedrock_login: self.bedrock_login,
         }
     }
+
+    /// Adds a virtual host, replacing any virtual host with the same hostname
+    ///
+    /// Hostnames are compared without a trailing `.` and case-insensitive.  
+    pub fn with_vhost(mut self, vhost: VirtualHost) -> Self {
+        let hostname = vhost.normalized_hostname();
+        self.vhosts.retain(|v| v.normalized_hostname() != hostname);
+        self.vhosts.push(vhost);
+        self
+    }
+
+    /// Removes virtual hosts that are replaced by a later one with the same hostname
+    pub(crate) fn dedup_vhosts(&mut self) {
+        let mut seen = HashSet::new();
+        let mut vhosts: Vec<_> = self
+            .vhosts
+            .drain(..)
+            .rev()
+            .filter(|v| seen.insert(v.normalized_hostname()))
+            .collect();
+        vhosts.reverse();
+        self.vhosts = vhosts;
+    }
 }
 
 impl Default for StatusConfig {
@@ -79,13 +458,99 @@ impl Default for StatusConfig {
                     .unwrap()
                     .into_rgba8(),
             ),
-            description: Some(
-                serde_json::to_value(serde_json::json!({
-                    "text": DEFAULT_DESC
-                }))
-                .unwrap(),
-            ),
-            legacy_decription: Some(DEFAULT_LEGACY_DESC.to_string()),
+            description: Some(TextComponent::text(DEFAULT_DESC)),
+            legacy_decription: None,
+            version_name: None,
+            protocol: None,
+            bedrock_description: Some(DEFAULT_BEDROCK_DESC.to_string()),
+            players: Some(StatusPlayers::default()),
         }
     }
 }
+
+/// Crops an image to a square and resizes it to the favicon size
+pub(crate) fn resize_favicon(image: DynamicImage, filter: FilterType) -> RgbaImage {
+    let size = StatusConfig::FAVICON_SIZE;
+    if image.width() == size && image.height() == size {
+        return image.into_rgba8();
+    }
+
+    image.resize_to_fill(size, size, filter).into_rgba8()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AlphabetToken;
+
+    #[test]
+    fn with_vhost_replaces_same_hostname() {
+        let config = ServerConfig::default()
+            .with_vhost(VirtualHost::new("play.example.com"))
+            .with_vhost(VirtualHost::new("*.example.com"))
+            .with_vhost(VirtualHost::new("Play.Example.com.").with_token(AlphabetToken::default()));
+
+        let hostnames: Vec<_> = config.vhosts.iter().map(|v| v.hostname.as_str()).collect();
+        assert_eq!(hostnames, ["*.example.com", "Play.Example.com."]);
+        assert!(format!("{:?}", config.vhosts[1].token).contains("AlphabetToken"));
+    }
+
+    #[test]
+    fn dedup_keeps_last() {
+        let mut config = ServerConfig {
+            vhosts: vec![
+                VirtualHost::new("play.example.com"),
+                VirtualHost::new("other.example.com"),
+                VirtualHost::new("PLAY.example.com").with_token(AlphabetToken::default()),
+            ],
+            ..Default::default()
+        };
+        config.dedup_vhosts();
+
+        let hostnames: Vec<_> = config.vhosts.iter().map(|v| v.hostname.as_str()).collect();
+        assert_eq!(hostnames, ["other.example.com", "PLAY.example.com"]);
+    }
+
+    #[test]
+    fn builders_keep_vhost_generators() {
+        let config = ServerConfig::default()
+            .with_vhost(VirtualHost::new("play.example.com").with_token(AlphabetToken::default()))
+            .with_token(Token)
+            .with_message(Message)
+            .with_status(StatusConfig::default());
+
+        assert!(format!("{:?}", config.vhosts[0].token).contains("AlphabetToken"));
+    }
+
+    #[test]
+    fn legacy_description_from_description() {
+        let status = StatusConfig {
+            description: Some(TextComponent::text("Link your account").color("green")),
+            ..Default::default()
+        };
+        assert_eq!(
+            status.legacy_description().to_legacy(),
+            "§aLink your account"
+        );
+
+        let status = StatusConfig {
+            legacy_decription: Some("§cToo old".to_string()),
+            ..status
+        };
+        assert_eq!(status.legacy_description().to_plain(), "Too old");
+    }
+
+    #[test]
+    fn specificity() {
+        let exact = VirtualHost::new("play.example.com");
+        let wildcard = VirtualHost::new("*.example.com");
+
+        assert_eq!(exact.specificity("play.example.com"), Some(usize::MAX));
+        assert_eq!(
+            wildcard.specificity("play.example.com"),
+            Some(".example.com".len())
+        );
+        assert_eq!(wildcard.specificity("example.com"), None);
+        assert_eq!(exact.specificity("other.example.com"), None);
+    }
+}
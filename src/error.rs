@@ -2,10 +2,12 @@ use std::any::Any;
 
 use crossbeam::channel::SendError;
 use rsa::pkcs8::spki;
-use simdnbt::owned::NbtTag;
 use thiserror::Error;
 
-use crate::{channel_message::ChannelMessage, minecraft::packet::Packet};
+use crate::{
+    channel_message::ChannelMessage,
+    minecraft::packet::{Packet, State},
+};
 
 /// Top-level Error structure  
 #[derive(Debug, Error)]
@@ -18,6 +20,14 @@ pub enum MCHAError {
     ThreadError(Box<dyn Any + Send + 'static>),
     #[error("Tried to shutdown server when no server is running")]
     NoServerRunning,
+    #[error("Tried to register a nonce without a nonce binding configured")]
+    NoNonceBinding,
+    #[error("Nonce is not a valid hostname label: {0}")]
+    InvalidNonce(String),
+    #[error("Failed to load favicon: {0}")]
+    InvalidFavicon(#[from] image::ImageError),
+    #[error("Token generator would create empty tokens: {0}")]
+    EmptyToken(&'static str),
     #[error("{0:?}")]
     ServerError(#[from] ServerError),
 }
@@ -51,10 +61,57 @@ pub enum ServerError {
     MismatchedVerifyTokens(Vec<u8>, Vec<u8>),
     #[error("Usernames were different during Authentication: {0} != {1}")]
     MismatchedUsernames(String, String),
-    #[error("Invalid NbtTag for message, must be either Compound, List(Compound) or String: {0:?}")]
-    InvalidMessageNbtTag(NbtTag),
     #[error("Intent in handshake is unknown: {0}")]
     UnknownHandshakeIntent(i32),
+    #[error("Failed to bind UDP port {0}: {1:?}")]
+    UdpBindError(u16, std::io::Error),
+    #[error("{0}")]
+    BedrockLoginError(#[from] BedrockLoginError),
+}
+
+/// Errors from a Bedrock Edition login, see [`ServerConfig::bedrock_login`](crate::ServerConfig::bedrock_login)  
+#[derive(Debug, Error)]
+pub enum BedrockLoginError {
+    #[error("Login packet is not valid: {0}")]
+    Malformed(&'static str),
+    #[error("Login chain signature does not match")]
+    InvalidSignature,
+    #[error("Login chain is not signed by Mojang, the player is not authenticated with Xbox Live")]
+    NotAuthenticated,
+    #[error("Login chain has expired or is not valid yet")]
+    Expired,
+    #[error("Compression algorithm is not supported: {0}")]
+    UnsupportedCompression(u8),
+}
+
+/// Errors from pinging a server with [`ping`](crate::ping) or [`ping_legacy`](crate::ping_legacy)  
+#[derive(Debug, Error)]
+pub enum PingError {
+    #[error("Failed to connect: {0:?}")]
+    ConnectError(std::io::Error),
+    #[error("Failed to create a packet type: {0:?}")]
+    TypeError(#[from] TypeError),
+    #[error("{0:?}")]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("Server responded with an invalid status: {0}")]
+    InvalidResponse(&'static str),
+    #[error("Host is too long for a legacy ping: {0} UTF-16 units")]
+    HostTooLong(usize),
+    #[error("Pong did not match the ping: {0} != {1}")]
+    MismatchedPong(i64, i64),
+}
+
+/// Errors from verifying a [`SignedToken`](crate::SignedToken)  
+#[derive(Debug, Error)]
+pub enum SignedTokenError {
+    #[error("Token is not a valid signed token")]
+    Malformed,
+    #[error("Token signature does not match")]
+    InvalidSignature,
+    #[error("Token has expired")]
+    Expired,
+    #[error("Token has already been verified")]
+    Replayed,
 }
 
 /// Errors related to writing and reading packets and their data types  
@@ -62,12 +119,26 @@ pub enum ServerError {
 pub enum TypeError {
     #[error("VarInt is too big, remaining value: {0}")]
     OversizedVarInt(i32),
+    #[error("VarLong is too big, remaining value: {0}")]
+    OversizedVarLong(i64),
+    #[error("Expected {0} more bytes, but only {1} are left")]
+    UnexpectedEnd(usize, usize),
+    #[error("Length prefix is not valid: {0}")]
+    InvalidLength(i32),
+    #[error("String is too long: {0} > {1}")]
+    StringTooLong(usize, usize),
+    #[error("Identifier is not valid: {0}")]
+    InvalidIdentifier(String),
     #[error("Packet is too big: {0} > {}", Packet::PACKET_LIMIT)]
     PacketSizeExceedsLimit(i32),
-    #[error("Expected packet id: {0}, but got {1}")]
-    UnexpectedPacketId(i32, i32),
-    #[error("Packet in beta legacy was too big: {0} != {0}")]
+    #[error("Expected {0:?} packet id: {1}, but got {2}")]
+    UnexpectedPacketId(State, i32, i32),
+    #[error("`{0}` is required for protocol version {1}, but is None")]
+    MissingField(&'static str, i32),
+    #[error("Packet in beta legacy was too big: {1} > {0}")]
     BetaLegacyPacketIsTooBig(u16, u16),
+    #[error("Legacy ping is not valid: {0}")]
+    InvalidLegacyPing(&'static str),
 
     #[error("Chunk tail was not empty when encrypting/decrypting packet, len:{0}")]
     ChunkTailIsNotEmpty(usize),
@@ -82,4 +153,6 @@ pub enum TypeError {
 
     #[error("{0:?}")]
     Utf8Error(#[from] std::string::FromUtf8Error),
+    #[error("Failed to read NBT: {0}")]
+    NbtError(#[from] simdnbt::Error),
 }
@@ -6,16 +6,30 @@ mod config;
 mod error;
 mod message;
 mod minecraft;
+mod nonce;
+mod ping;
 mod player;
 mod server;
+mod signed_token;
+mod status;
+mod text;
 mod token;
 
-pub use config::{ServerConfig, StatusConfig};
-pub use error::{MCHAError, ServerError, TypeError};
-pub use message::{Message, MessageGenerator};
+pub use config::{NonceBinding, ReissuePolicy, ServerConfig, StatusConfig, VirtualHost};
+pub use error::{
+    BedrockLoginError, MCHAError, PingError, ServerError, SignedTokenError, TypeError,
+};
+pub use message::{Message, MessageContext, MessageGenerator};
+pub use ping::{LegacyPing, LegacyPingKind, Ping, ping, ping_legacy};
 pub use player::Player;
-pub use server::Server;
-pub use token::{Token, TokenGenerator};
+pub use server::{Server, VhostTokens};
+pub use signed_token::{SignedClaims, SignedToken};
+pub use status::{
+    SamplePlayer, StatusPlayers, StatusProvider, StatusRequest, StatusResponse,
+    StatusResponsePlayers, StatusVersion,
+};
+pub use text::{ClickEvent, Content, HoverEvent, TextComponent};
+pub use token::{AlphabetToken, NumericToken, Token, TokenGenerator, WordToken, storage::Claim};
 
 pub use image;
 pub use serde_json;
@@ -1,30 +1,163 @@
-use simdnbt::owned::*;
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
+
+use crate::text::TextComponent;
 
 /// Creates a text component message with a token as input  
-pub trait MessageGenerator: Debug + Clone {
-    fn create_message(self, token: &str) -> NbtTag;
+pub trait MessageGenerator: Debug + Clone + Send + Sync + 'static {
+    fn create_message(self, ctx: &MessageContext) -> TextComponent;
+}
+
+/// [`MessageGenerator`] without `Clone`, so generators of different types can be mixed
+pub(crate) trait DynMessageGenerator: Debug + Send + Sync + 'static {
+    fn create_message(&self, ctx: &MessageContext) -> TextComponent;
+}
+
+impl<M: MessageGenerator> DynMessageGenerator for M {
+    fn create_message(&self, ctx: &MessageContext) -> TextComponent {
+        MessageGenerator::create_message(self.clone(), ctx)
+    }
+}
+
+/// A message generator of any type, like the ones of [`VirtualHost`](crate::VirtualHost)s
+pub(crate) type AnyMessageGenerator = Arc<dyn DynMessageGenerator>;
+
+impl MessageGenerator for AnyMessageGenerator {
+    fn create_message(self, ctx: &MessageContext) -> TextComponent {
+        DynMessageGenerator::create_message(self.as_ref(), ctx)
+    }
+}
+
+/// Everything known about the player when their message is created  
+#[derive(Debug, Clone)]
+pub struct MessageContext<'a> {
+    /// The token as it should be displayed, see [`TokenGenerator::display`](crate::TokenGenerator::display)
+    pub token: &'a str,
+    /// The token as it was generated, this is what should be copied
+    pub raw_token: &'a str,
+    /// The protocol version of the client
+    pub protocol_version: i32,
+    /// The locale of the client like `en_us`, only known with [`ServerConfig::wait_for_locale`](crate::ServerConfig::wait_for_locale)  
+    /// or for Bedrock Edition players
+    pub locale: Option<&'a str>,
+    /// Whether the player joined with Bedrock Edition, see [`ServerConfig::bedrock_login`](crate::ServerConfig::bedrock_login)
+    ///
+    /// Bedrock shows the message as [`TextComponent::to_legacy`], so nothing can be clicked or hovered.  
+    /// [`MessageContext::protocol_version`] is then a Bedrock protocol version.  
+    pub bedrock: bool,
 }
 
 /// The default message template displayed upon disconnect  
+///
+/// Clicking the token copies it to the clipboard.  
+/// The text is translated if the locale of the client is known.  
 #[derive(Debug, Clone)]
 pub struct Message;
+
+/// Locale, label, hint and hover text of the default message
+const TRANSLATIONS: &[(&str, &str, &str, &str)] = &[
+    (
+        "en_us",
+        "Token: ",
+        "Use this to link your\nminecraft account",
+        "Click to copy",
+    ),
+    (
+        "de_de",
+        "Token: ",
+        "Verwende diesen, um deinen\nMinecraft-Account zu verknüpfen",
+        "Klicken zum Kopieren",
+    ),
+    (
+        "es_es",
+        "Código: ",
+        "Úsalo para vincular tu\ncuenta de Minecraft",
+        "Haz clic para copiar",
+    ),
+    (
+        "fr_fr",
+        "Jeton : ",
+        "Utilise-le pour lier ton\ncompte Minecraft",
+        "Clique pour copier",
+    ),
+    (
+        "it_it",
+        "Token: ",
+        "Usalo per collegare il tuo\naccount Minecraft",
+        "Clicca per copiare",
+    ),
+    (
+        "nl_nl",
+        "Token: ",
+        "Gebruik deze om je\nMinecraft-account te koppelen",
+        "Klik om te kopiëren",
+    ),
+    (
+        "pl_pl",
+        "Token: ",
+        "Użyj go, aby połączyć swoje\nkonto Minecraft",
+        "Kliknij, aby skopiować",
+    ),
+    (
+        "pt_br",
+        "Token: ",
+        "Use-o para vincular sua\nconta do Minecraft",
+        "Clique para copiar",
+    ),
+    (
+        "ru_ru",
+        "Токен: ",
+        "Используйте его, чтобы привязать\nаккаунт Minecraft",
+        "Нажмите, чтобы скопировать",
+    ),
+    (
+        "sv_se",
+        "Token: ",
+        "Använd den för att länka ditt\nMinecraft-konto",
+        "Klicka för att kopiera",
+    ),
+    (
+        "ja_jp",
+        "トークン: ",
+        "Minecraftアカウントを\nリンクするために使ってください",
+        "クリックしてコピー",
+    ),
+    (
+        "zh_cn",
+        "令牌：",
+        "使用它来关联你的\nMinecraft 账户",
+        "点击复制",
+    ),
+];
+
+/// Finds the translation of a locale, falling back to the same language and then `en_us`
+fn translation(locale: Option<&str>) -> (&'static str, &'static str, &'static str) {
+    let locale = locale.unwrap_or_default().to_lowercase();
+    let language = locale.split('_').next().unwrap_or_default();
+
+    let (_, label, hint, hover) = TRANSLATIONS
+        .iter()
+        .find(|(l, ..)| *l == locale)
+        .or_else(|| {
+            TRANSLATIONS
+                .iter()
+                .find(|(l, ..)| l.split('_').next() == Some(language))
+        })
+        .unwrap_or(&TRANSLATIONS[0]);
+
+    (label, hint, hover)
+}
+
 impl MessageGenerator for Message {
-    fn create_message(self, token: &str) -> NbtTag {
-        let mut text = NbtCompound::new();
-        text.insert("text", NbtTag::String("Token: ".into()));
-
-        let mut token_txt = NbtCompound::new();
-        token_txt.insert("text", NbtTag::String(token.into()));
-        token_txt.insert("color", NbtTag::String("#36bf5a".into()));
-
-        let mut desc = NbtCompound::new();
-        desc.insert(
-            "text",
-            NbtTag::String("\n\nUse this to link your\nminecraft account".into()),
-        );
-        desc.insert("color", "#919191");
-
-        NbtTag::List(NbtList::Compound(vec![text, token_txt, desc]))
+    fn create_message(self, ctx: &MessageContext) -> TextComponent {
+        let (label, hint, hover) = translation(ctx.locale);
+
+        TextComponent::text(label)
+            .extra(
+                TextComponent::text(ctx.token)
+                    .color("#36bf5a")
+                    .copy_on_click(ctx.raw_token)
+                    .hover_text(hover),
+            )
+            .extra(TextComponent::text(format!("\n\n{hint}")).color("#919191"))
     }
 }
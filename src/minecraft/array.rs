@@ -25,6 +25,11 @@ impl<T: WritePacketData> Array<T> {
     pub fn as_ref(&self) -> &Vec<T> {
         &self.data
     }
+
+    #[allow(unused)]
+    pub fn into_inner(self) -> Vec<T> {
+        self.data
+    }
 }
 
 impl<T: WritePacketData> WritePacketData for Array<T> {
@@ -36,12 +41,48 @@ impl<T: WritePacketData> WritePacketData for Array<T> {
     }
 }
 
-impl ReadPacketData for Array<u8> {
+impl<T: ReadPacketData + WritePacketData> ReadPacketData for Array<T> {
     fn read(data: &mut bytes::Bytes) -> Result<Self, TypeError> {
         let len = VarInt::read(data)?;
+        // every item takes at least a byte, so a longer array can't fit and isn't worth allocating
+        if len.0 < 0 || len.0 as usize > data.remaining() {
+            return Err(TypeError::InvalidLength(len.0));
+        }
 
-        let v_data = data.copy_to_bytes(len.0 as usize).to_vec();
+        let v_data = (0..len.0)
+            .map(|_| T::read(data))
+            .collect::<Result<Vec<_>, _>>()?;
 
         Ok(Array { len, data: v_data })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use proptest::prelude::*;
+
+    use crate::minecraft::packet::{ReadPacketData, WritePacketData};
+
+    use super::Array;
+
+    #[test]
+    fn fixture() {
+        let mut data = BytesMut::new();
+        Array::new(vec![0xCAu8, 0xFE]).write(&mut data);
+        assert_eq!(&data[..], [0x02, 0xCA, 0xFE]);
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(value: Vec<u8>) {
+            let mut data = BytesMut::new();
+            Array::new(value.clone()).write(&mut data);
+
+            let mut data = data.freeze();
+            let array = Array::<u8>::read(&mut data).unwrap();
+            prop_assert_eq!(array.as_ref(), &value);
+            prop_assert!(data.is_empty());
+        }
+    }
+}
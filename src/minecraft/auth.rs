@@ -2,6 +2,7 @@ use std::{net::TcpStream, sync::Arc};
 
 use aes::cipher::generic_array;
 use der::Encode;
+use mc_headless_auth_derive::Packet;
 use rand::RngExt;
 use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey, pkcs8::SubjectPublicKeyInfo};
 use serde::Deserialize;
@@ -15,9 +16,10 @@ use crate::{
         encrypt::{Aes128CfbDec, Aes128CfbEnc, create_cipher},
         hash::notchian_digest,
         login_start::LoginStart,
-        packet::{Packet, ReadPacketData},
+        packet::{Packet, ReadPacketData, State, VersionedPacket, WritePacketData},
         packets, protocol_version,
-        uuid::uuid_to_bytes,
+        string::PacketString,
+        var_int::VarInt,
     },
 };
 
@@ -41,10 +43,31 @@ pub struct GameProfileProps {
     pub signature: Option<String>,
 }
 
+impl ReadPacketData for GameProfileProps {
+    fn read(data: &mut bytes::Bytes) -> Result<Self, TypeError> {
+        let name: PacketString = PacketString::read(data)?;
+        let value: PacketString = PacketString::read(data)?;
+        let signature: Option<PacketString> = Option::read(data)?;
+
+        Ok(Self {
+            name: name.0,
+            value: value.0,
+            signature: signature.map(|s| s.0),
+        })
+    }
+}
+
+impl WritePacketData for GameProfileProps {
+    fn write(self, data: &mut bytes::BytesMut) {
+        PacketString::new(self.name).write(data);
+        PacketString::new(self.value).write(data);
+        self.signature.map(PacketString::new).write(data);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct AuthResponse {
     pub enc: Aes128CfbEnc,
-    #[allow(unused)]
     pub dec: Aes128CfbDec,
     pub profile: GameProfile,
 }
@@ -62,17 +85,17 @@ pub fn authenticate(
     let mut token = vec![0; 64];
     rand::rng().fill(&mut token);
 
-    packets::encryption_request(
-        SERVER_ID,
-        encoded_public_key.clone(),
-        token.clone(),
-        protocol_version > protocol_version::V1_20_5,
-    )
+    packets::EncryptionRequest {
+        server_id: PacketString(SERVER_ID.to_string()),
+        public_key: Array::new(encoded_public_key.clone()),
+        verify_token: Array::new(token.clone()),
+        should_authenticate: Some(protocol_version > protocol_version::V1_20_5),
+    }
+    .into_packet(protocol_version)?
     .write_stream(stream)?;
 
     // 6. Encryption Response
-    let mut response = Packet::from_stream(stream, 0x01)?;
-    let response = EncryptionResponse::read(&mut response.data)?;
+    let response = EncryptionResponse::from_stream(stream, protocol_version)?;
 
     let shared_secret = private_key.decrypt(Pkcs1v15Encrypt, response.shared_secret.as_ref())?;
     let verify_token = private_key.decrypt(Pkcs1v15Encrypt, response.verify_token.as_ref())?;
@@ -97,15 +120,21 @@ pub fn authenticate(
 
     let (mut enc, mut dec) = create_cipher(&shared_secret)?;
 
-    packets::set_compression(0).write_encrypted_stream(stream, &mut enc)?;
-    packets::login_success(
-        uuid_to_bytes(profile.id, protocol_version),
-        &profile.name,
-        profile.properties.clone(),
-    )
+    packets::SetCompression {
+        threshold: VarInt(0),
+    }
+    .into_packet(protocol_version)?
+    .write_encrypted_stream(stream, &mut enc)?;
+    packets::LoginSuccess {
+        uuid: profile.id,
+        username: PacketString(profile.name.clone()),
+        properties: Array::new(profile.properties.clone()),
+        strict_error_handling: Some(true),
+    }
+    .into_packet(protocol_version)?
     .write_compressed_encrypted_stream(stream, &mut enc)?;
 
-    let _ = Packet::from_compressed_encrypted_stream(stream, &mut dec, 0x03)?;
+    let _ = Packet::from_compressed_encrypted_stream(stream, &mut dec, State::Login, 0x03)?;
 
     return Ok(AuthResponse { enc, dec, profile });
 }
@@ -147,20 +176,28 @@ pub fn gen_rsa_key() -> (Arc<RsaPrivateKey>, Arc<RsaPublicKey>) {
     (Arc::new(priv_key), Arc::new(pub_key))
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Packet)]
+#[packet(id = 0x01, state = Login)]
 struct EncryptionResponse {
     shared_secret: Array<u8>,
     verify_token: Array<u8>,
 }
 
-impl ReadPacketData for EncryptionResponse {
-    fn read(data: &mut bytes::Bytes) -> Result<Self, TypeError> {
-        let shared = Array::<u8>::read(data)?;
-        let token = Array::<u8>::read(data)?;
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
 
-        Ok(Self {
-            shared_secret: shared,
-            verify_token: token,
-        })
+    use crate::minecraft::packet::ReadPacketData;
+
+    use super::EncryptionResponse;
+
+    #[test]
+    fn read_encryption_response() {
+        let mut data = Bytes::from_static(b"\x03\x01\x02\x03\x04\x0A\x0B\x0C\x0D");
+        let response = EncryptionResponse::read(&mut data).unwrap();
+
+        assert_eq!(response.shared_secret.as_ref(), &[0x01, 0x02, 0x03]);
+        assert_eq!(response.verify_token.as_ref(), &[0x0A, 0x0B, 0x0C, 0x0D]);
+        assert!(data.is_empty());
     }
 }
@@ -0,0 +1,359 @@
+use base64::prelude::*;
+use chrono::Utc;
+use p384::{
+    ecdsa::{Signature, VerifyingKey, signature::Verifier},
+    pkcs8::DecodePublicKey,
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use uuid::Uuid;
+
+use crate::error::BedrockLoginError;
+
+// https://minecraft.wiki/w/Bedrock_Edition_protocol/Login
+
+/// The public key Mojang signs the chain of every Xbox Live authenticated player with
+const MOJANG_PUBLIC_KEY: &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAECRXueJeTDqNRRgJi/vlRufByu/2G0i2Ebt6YMar5QX/R0DIIyrJMcUpruK4QveTfJSTp3Shlq4Gk34cD/4GUWwkv0DVuzeuB+tXija7HBxii03NHDbPAD0AKnLr2wdAp";
+/// Allowed clock difference between us and the client or Mojang, in seconds
+const LEEWAY: i64 = 60;
+
+/// Who the player is, as signed by Mojang
+#[derive(Debug, Clone)]
+pub struct Identity {
+    pub xuid: String,
+    pub display_name: String,
+    pub uuid: Uuid,
+}
+
+/// What the client says about itself, signed by the key at the end of the chain
+#[derive(Debug, Clone, Default)]
+pub struct ClientData {
+    /// Like `en_US`
+    pub locale: Option<String>,
+    /// The address the player typed in, like `play.example.com:19132`
+    pub server_address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Chain {
+    chain: Vec<String>,
+}
+
+/// Since 1.21.90 the chain is wrapped in a certificate next to an OpenID token
+#[derive(Debug, Deserialize)]
+struct Certificate {
+    #[serde(rename = "Certificate")]
+    certificate: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    x5u: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Claims {
+    identity_public_key: String,
+    exp: Option<i64>,
+    nbf: Option<i64>,
+    extra_data: Option<ExtraData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExtraData {
+    #[serde(rename = "XUID")]
+    xuid: String,
+    #[serde(rename = "displayName")]
+    display_name: String,
+    identity: Uuid,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ClientClaims {
+    language_code: Option<String>,
+    server_address: Option<String>,
+}
+
+/// Verifies the login chain and client data of a Login packet, without asking Xbox Live
+///
+/// Each token in the chain is signed by the key the previous one names in `identityPublicKey`.
+/// A player is only authenticated if one of them is signed by Mojang,
+/// and only what comes after that signature is trusted.
+pub fn verify(chain: &str, client_data: &str) -> Result<(Identity, ClientData), BedrockLoginError> {
+    verify_with(chain, client_data, MOJANG_PUBLIC_KEY)
+}
+
+/// [`verify`] with another key in place of Mojangs
+fn verify_with(
+    chain: &str,
+    client_data: &str,
+    trusted_key: &str,
+) -> Result<(Identity, ClientData), BedrockLoginError> {
+    let chain = match serde_json::from_str::<Chain>(chain) {
+        Ok(chain) => chain,
+        Err(_) => {
+            let certificate = serde_json::from_str::<Certificate>(chain)
+                .map_err(|_| BedrockLoginError::Malformed("chain"))?;
+            serde_json::from_str::<Chain>(&certificate.certificate)
+                .map_err(|_| BedrockLoginError::Malformed("chain"))?
+        }
+    };
+
+    let mut expected_key: Option<String> = None;
+    let mut authenticated = false;
+    let mut identity = None;
+
+    for jwt in &chain.chain {
+        let (header, claims) = decode::<Claims>(jwt, expected_key.as_deref())?;
+        check_time(claims.exp, claims.nbf)?;
+
+        if header.x5u == trusted_key {
+            authenticated = true;
+        }
+        if authenticated && let Some(extra) = claims.extra_data {
+            identity = Some(Identity {
+                xuid: extra.xuid,
+                display_name: extra.display_name,
+                uuid: extra.identity,
+            });
+        }
+
+        expected_key = Some(claims.identity_public_key);
+    }
+
+    let identity = match identity {
+        Some(identity) if !identity.xuid.is_empty() => identity,
+        _ => return Err(BedrockLoginError::NotAuthenticated),
+    };
+
+    // signed by the players own key, so it can't be taken from someone elses login
+    let (_, client) = decode::<ClientClaims>(
+        client_data,
+        Some(expected_key.as_deref().unwrap_or_default()),
+    )?;
+
+    Ok((
+        identity,
+        ClientData {
+            locale: client.language_code,
+            server_address: client.server_address,
+        },
+    ))
+}
+
+/// Decodes a JWT and checks its ES384 signature, against `expected_key` if there is one
+fn decode<C: DeserializeOwned>(
+    jwt: &str,
+    expected_key: Option<&str>,
+) -> Result<(Header, C), BedrockLoginError> {
+    let mut parts = jwt.split('.');
+    let (Some(header), Some(claims), Some(signature), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(BedrockLoginError::Malformed("jwt"));
+    };
+    // the signature covers the header and claims as they were sent
+    let signed = &jwt[..header.len() + 1 + claims.len()];
+
+    let header: Header = serde_json::from_slice(&decode_base64url(header)?)
+        .map_err(|_| BedrockLoginError::Malformed("jwt header"))?;
+    if header.alg != "ES384" {
+        return Err(BedrockLoginError::Malformed("jwt algorithm"));
+    }
+    if expected_key.is_some_and(|key| key != header.x5u) {
+        return Err(BedrockLoginError::InvalidSignature);
+    }
+
+    let der = BASE64_STANDARD
+        .decode(&header.x5u)
+        .map_err(|_| BedrockLoginError::Malformed("public key"))?;
+    let key = VerifyingKey::from_public_key_der(&der)
+        .map_err(|_| BedrockLoginError::Malformed("public key"))?;
+    let signature = Signature::from_slice(&decode_base64url(signature)?)
+        .map_err(|_| BedrockLoginError::InvalidSignature)?;
+
+    key.verify(signed.as_bytes(), &signature)
+        .map_err(|_| BedrockLoginError::InvalidSignature)?;
+
+    let claims = serde_json::from_slice(&decode_base64url(claims)?)
+        .map_err(|_| BedrockLoginError::Malformed("jwt claims"))?;
+
+    Ok((header, claims))
+}
+
+fn decode_base64url(data: &str) -> Result<Vec<u8>, BedrockLoginError> {
+    BASE64_URL_SAFE_NO_PAD
+        .decode(data.trim_end_matches('='))
+        .map_err(|_| BedrockLoginError::Malformed("base64"))
+}
+
+fn check_time(exp: Option<i64>, nbf: Option<i64>) -> Result<(), BedrockLoginError> {
+    let now = Utc::now().timestamp();
+
+    if exp.is_some_and(|exp| exp + LEEWAY < now) || nbf.is_some_and(|nbf| nbf - LEEWAY > now) {
+        return Err(BedrockLoginError::Expired);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use p384::{
+        ecdsa::{SigningKey, signature::Signer},
+        pkcs8::EncodePublicKey,
+    };
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const UUID: &str = "a1b2c3d4-0000-3000-8000-000000000001";
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_slice(&[seed; 48]).unwrap()
+    }
+
+    fn public(key: &SigningKey) -> String {
+        let der = key.verifying_key().to_public_key_der().unwrap();
+        BASE64_STANDARD.encode(der.as_bytes())
+    }
+
+    fn jwt(key: &SigningKey, claims: Value) -> String {
+        let header = json!({ "alg": "ES384", "x5u": public(key) });
+        let signed = format!(
+            "{}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(header.to_string()),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let signature: Signature = key.sign(signed.as_bytes());
+
+        format!(
+            "{signed}.{}",
+            BASE64_URL_SAFE_NO_PAD.encode(signature.to_bytes())
+        )
+    }
+
+    fn claims(identity_public_key: &SigningKey, exp_offset: i64) -> Value {
+        let now = Utc::now().timestamp();
+        json!({
+            "identityPublicKey": public(identity_public_key),
+            "exp": now + exp_offset,
+            "nbf": now - 60,
+        })
+    }
+
+    /// A chain like the client sends it, the client names the root, which vouches for the client
+    fn chain(client: &SigningKey, root: &SigningKey, exp_offset: i64) -> String {
+        let mut identity = claims(client, exp_offset);
+        identity["extraData"] = json!({
+            "XUID": "2535400000000001",
+            "displayName": "Steve Gamer",
+            "identity": UUID,
+        });
+
+        json!({ "chain": [jwt(client, claims(root, 3600)), jwt(root, identity)] }).to_string()
+    }
+
+    fn client_data(client: &SigningKey) -> String {
+        jwt(
+            client,
+            json!({ "LanguageCode": "de_DE", "ServerAddress": "play.example.com:19132" }),
+        )
+    }
+
+    #[test]
+    fn trusted_chain() {
+        let (client, root) = (key(1), key(2));
+
+        let (identity, client) = verify_with(
+            &chain(&client, &root, 3600),
+            &client_data(&client),
+            &public(&root),
+        )
+        .unwrap();
+        assert_eq!(identity.xuid, "2535400000000001");
+        assert_eq!(identity.display_name, "Steve Gamer");
+        assert_eq!(identity.uuid.to_string(), UUID);
+        assert_eq!(client.locale.as_deref(), Some("de_DE"));
+        assert_eq!(
+            client.server_address.as_deref(),
+            Some("play.example.com:19132")
+        );
+    }
+
+    #[test]
+    fn certificate_chain() {
+        let (client, root) = (key(1), key(2));
+        let certificate = json!({ "Certificate": chain(&client, &root, 3600) }).to_string();
+
+        assert!(verify_with(&certificate, &client_data(&client), &public(&root)).is_ok());
+    }
+
+    #[test]
+    fn self_signed() {
+        let (client, root) = (key(1), key(2));
+
+        let result = verify(&chain(&client, &root, 3600), &client_data(&client));
+        assert!(matches!(result, Err(BedrockLoginError::NotAuthenticated)));
+    }
+
+    #[test]
+    fn broken_link() {
+        let (client, root, other) = (key(1), key(2), key(3));
+        let mut identity = claims(&client, 3600);
+        identity["extraData"] = json!({ "XUID": "1", "displayName": "Steve", "identity": UUID });
+        // the first token names `other`, but the next one is signed by `root`
+        let chain = json!({ "chain": [jwt(&client, claims(&other, 3600)), jwt(&root, identity)] });
+
+        let result = verify_with(&chain.to_string(), &client_data(&client), &public(&root));
+        assert!(matches!(result, Err(BedrockLoginError::InvalidSignature)));
+    }
+
+    #[test]
+    fn expired() {
+        let (client, root) = (key(1), key(2));
+
+        let result = verify_with(
+            &chain(&client, &root, -3600),
+            &client_data(&client),
+            &public(&root),
+        );
+        assert!(matches!(result, Err(BedrockLoginError::Expired)));
+    }
+
+    #[test]
+    fn tampered_client_data() {
+        let (client, root) = (key(1), key(2));
+        let chain = chain(&client, &root, 3600);
+
+        let data = client_data(&client);
+        let mut parts: Vec<_> = data.split('.').collect();
+        let claims = BASE64_URL_SAFE_NO_PAD
+            .encode(json!({ "ServerAddress": "other.example.com:19132" }).to_string());
+        parts[1] = &claims;
+        let result = verify_with(&chain, &parts.join("."), &public(&root));
+        assert!(matches!(result, Err(BedrockLoginError::InvalidSignature)));
+
+        // signed, but not by the key the chain ends with
+        let result = verify_with(&chain, &client_data(&key(3)), &public(&root));
+        assert!(matches!(result, Err(BedrockLoginError::InvalidSignature)));
+    }
+
+    #[test]
+    fn malformed_jwt() {
+        let client = key(1);
+        let header = BASE64_URL_SAFE_NO_PAD.encode(json!({ "alg": "none", "x5u": "" }).to_string());
+
+        assert!(matches!(
+            decode::<Value>("not a jwt", None),
+            Err(BedrockLoginError::Malformed("jwt"))
+        ));
+        assert!(matches!(
+            decode::<Value>(&format!("{header}.e30."), None),
+            Err(BedrockLoginError::Malformed("jwt algorithm"))
+        ));
+        assert!(decode::<Value>(&client_data(&client), Some(&public(&client))).is_ok());
+    }
+}
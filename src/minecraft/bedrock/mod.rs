@@ -0,0 +1,574 @@
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    net::{SocketAddr, UdpSocket},
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use constcat::concat;
+use crossbeam::channel::{Receiver, TryRecvError};
+use miniz_oxide::{deflate, inflate};
+use rand::RngExt;
+
+use crate::{
+    Player, ServerError,
+    channel_message::{ChannelMessage, MessageData},
+    config::{MIN_SUPPORTED_VERSION, ServerConfig},
+    error::BedrockLoginError,
+    message::{MessageContext, MessageGenerator},
+    minecraft::{
+        bedrock::raknet::{Connection, OFFLINE_MESSAGE_ID, ensure},
+        handshake::normalize_host,
+        intents::login::{complete_join, issue_token},
+        packet::WritePacketData,
+        server::ConnectionState,
+        var_int::VarInt,
+    },
+    status::{StatusProvider, StatusRequest},
+    token::TokenGenerator,
+};
+
+mod chain;
+mod raknet;
+
+/// Answers [RakNet unconnected pings](https://minecraft.wiki/w/Bedrock_Edition_protocol/RakNet#Unconnected_Ping) from Bedrock Edition clients
+///
+/// Bedrock players can't join a Java server, but this way the server list tells them why.
+/// With [`ServerConfig::bedrock_login`] they can join, and get disconnected with their token like Java players.
+/// Runs on its own thread, so a slow login or a flood of datagrams doesn't hold up Java connections.
+#[derive(Debug)]
+pub struct BedrockListener {
+    socket: UdpSocket,
+    port: u16,
+    guid: i64,
+    login: bool,
+    sessions: HashMap<SocketAddr, Session>,
+}
+
+/// A connected client going through the login
+#[derive(Debug)]
+struct Session {
+    connection: Connection,
+    /// Sent in the network settings request, before that nothing is compressed
+    protocol: Option<i32>,
+    compression: bool,
+    /// When the client was disconnected, the connection is closed a bit later so the message arrives
+    disconnected: Option<Instant>,
+}
+
+impl BedrockListener {
+    const UNCONNECTED_PING: u8 = 0x01;
+    const UNCONNECTED_PING_OPEN_CONNECTIONS: u8 = 0x02;
+    const UNCONNECTED_PONG: u8 = 0x1C;
+    /// Id + time + magic + client guid
+    const PING_LEN: usize = 1 + 8 + 16 + 8;
+    /// Never matches a real Bedrock version, so the server is shown as incompatible
+    const PROTOCOL: i32 = 1;
+    /// A recent Bedrock version, so the server isn't shown as incompatible when players can join.
+    /// The login works the same for every version since 1.19.30, so this doesn't have to match the client.
+    const LOGIN_PROTOCOL: i32 = 844;
+
+    const MIN_MTU: u16 = 576;
+    const MAX_MTU: u16 = 1492;
+    /// IP and UDP header, which the client counts in its MTU
+    const UDP_OVERHEAD: usize = 28;
+    const TIMEOUT: Duration = Duration::from_secs(10);
+    /// Same as for Java, if the connection closes right away the client shows "disconnected"
+    const CLOSE_AFTER: Duration = Duration::from_millis(2500);
+    /// How long a poll waits for a datagram, also how often sessions are flushed when idle
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+    /// Datagrams handled per poll, so sessions are still flushed and timed out during a flood
+    const MAX_DATAGRAMS_PER_POLL: usize = 256;
+    /// Clients logging in at the same time, the source address of a datagram can be spoofed
+    const MAX_SESSIONS: usize = 256;
+
+    pub fn bind(port: u16, login: bool) -> Result<Self, ServerError> {
+        let socket = UdpSocket::bind(format!("0.0.0.0:{port}"))
+            .and_then(|socket| {
+                socket.set_read_timeout(Some(Self::POLL_INTERVAL))?;
+                Ok(socket)
+            })
+            .map_err(|e| ServerError::UdpBindError(port, e))?;
+
+        Ok(Self {
+            socket,
+            port,
+            guid: rand::rng().random(),
+            login,
+            sessions: HashMap::new(),
+        })
+    }
+
+    /// Polls on a new thread until `stop` is closed
+    pub fn spawn<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
+        mut self,
+        config: Arc<ServerConfig<T, M, S>>,
+        state: ConnectionState,
+        stop: Receiver<()>,
+    ) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while let Err(TryRecvError::Empty) = stop.try_recv() {
+                self.poll(&config, &state);
+            }
+        })
+    }
+
+    /// Answers the packets that are waiting, anything unexpected is ignored
+    fn poll<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
+        &mut self,
+        config: &ServerConfig<T, M, S>,
+        state: &ConnectionState,
+    ) {
+        let mut buf = [0u8; 1500];
+        for _ in 0..Self::MAX_DATAGRAMS_PER_POLL {
+            let (len, addr) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    break;
+                }
+                // whatever is left gets read on the next poll
+                Err(_) => break,
+            };
+
+            self.handle(&buf[..len], addr, config, state);
+        }
+
+        self.sessions.retain(|_, session| {
+            let closed = session
+                .disconnected
+                .is_some_and(|at| at.elapsed() >= Self::CLOSE_AFTER);
+            if closed {
+                session.connection.disconnect(&self.socket);
+            } else {
+                session.connection.flush(&self.socket);
+            }
+
+            !closed && session.connection.last_seen.elapsed() < Self::TIMEOUT
+        });
+    }
+
+    fn handle<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
+        &mut self,
+        data: &[u8],
+        addr: SocketAddr,
+        config: &ServerConfig<T, M, S>,
+        state: &ConnectionState,
+    ) {
+        match data.first() {
+            Some(&Self::UNCONNECTED_PING | &Self::UNCONNECTED_PING_OPEN_CONNECTIONS) => {
+                self.pong(data, addr, &config.status)
+            }
+            Some(_) if !self.login => (),
+            Some(&raknet::OPEN_CONNECTION_REQUEST_1) => self.open_connection_1(data, addr),
+            Some(&raknet::OPEN_CONNECTION_REQUEST_2) => self.open_connection_2(data, addr),
+            Some(_) => {
+                let Some(session) = self.sessions.get_mut(&addr) else {
+                    return;
+                };
+
+                let result = session
+                    .connection
+                    .receive(&self.socket, Bytes::copy_from_slice(data))
+                    .map_err(ServerError::from)
+                    .and_then(|packets| {
+                        packets.into_iter().try_for_each(|packet| {
+                            session.handle(&self.socket, packet, config, state)
+                        })
+                    });
+
+                if let Err(e) = result {
+                    session.connection.disconnect(&self.socket);
+                    self.sessions.remove(&addr);
+                    state
+                        .broadcast
+                        .send(ChannelMessage::new(MessageData::ConnectionError(Box::new(
+                            e,
+                        ))));
+                }
+            }
+            None => (),
+        }
+    }
+
+    fn pong<S: StatusProvider>(&self, mut data: &[u8], addr: SocketAddr, status: &S) {
+        if data.len() < Self::PING_LEN {
+            return;
+        }
+        data.advance(1);
+        let time = data.get_i64();
+        if data[..16] != OFFLINE_MESSAGE_ID {
+            return;
+        }
+
+        let motd = self.motd(status);
+        let mut res = BytesMut::new();
+        res.put_u8(Self::UNCONNECTED_PONG);
+        res.put_i64(time);
+        res.put_i64(self.guid);
+        res.extend_from_slice(&OFFLINE_MESSAGE_ID);
+        res.put_u16(motd.len() as u16);
+        res.extend_from_slice(motd.as_bytes());
+
+        let _ = self.socket.send_to(&res, addr);
+    }
+
+    /// The client pads this request to find out how big a datagram can be
+    fn open_connection_1(&self, data: &[u8], addr: SocketAddr) {
+        // id + magic + protocol
+        if data.len() < 1 + 16 + 1 || data[1..17] != OFFLINE_MESSAGE_ID {
+            return;
+        }
+
+        let mut res = BytesMut::new();
+        if data[17] != raknet::PROTOCOL {
+            res.put_u8(raknet::INCOMPATIBLE_PROTOCOL);
+            res.put_u8(raknet::PROTOCOL);
+            res.extend_from_slice(&OFFLINE_MESSAGE_ID);
+            res.put_i64(self.guid);
+        } else {
+            let mtu = (data.len() + Self::UDP_OVERHEAD).min(Self::MAX_MTU as usize);
+
+            res.put_u8(raknet::OPEN_CONNECTION_REPLY_1);
+            res.extend_from_slice(&OFFLINE_MESSAGE_ID);
+            res.put_i64(self.guid);
+            // no security
+            res.put_u8(0);
+            res.put_u16(mtu as u16);
+        }
+
+        let _ = self.socket.send_to(&res, addr);
+    }
+
+    fn open_connection_2(&mut self, data: &[u8], addr: SocketAddr) {
+        let mut data = Bytes::copy_from_slice(data);
+        // id + magic
+        if data.len() < 1 + 16 || data[1..17] != OFFLINE_MESSAGE_ID {
+            return;
+        }
+        data.advance(17);
+        // the server address as the client sees it
+        if raknet::get_address(&mut data).is_err() || ensure(&data, 2).is_err() {
+            return;
+        }
+        let mtu = data.get_u16().clamp(Self::MIN_MTU, Self::MAX_MTU);
+
+        let mut res = BytesMut::new();
+        if !self.sessions.contains_key(&addr) && self.sessions.len() >= Self::MAX_SESSIONS {
+            res.put_u8(raknet::NO_FREE_INCOMING_CONNECTIONS);
+            res.extend_from_slice(&OFFLINE_MESSAGE_ID);
+            res.put_i64(self.guid);
+            let _ = self.socket.send_to(&res, addr);
+            return;
+        }
+
+        res.put_u8(raknet::OPEN_CONNECTION_REPLY_2);
+        res.extend_from_slice(&OFFLINE_MESSAGE_ID);
+        res.put_i64(self.guid);
+        raknet::put_address(&mut res, addr);
+        res.put_u16(mtu);
+        // no encryption
+        res.put_u8(0);
+        let _ = self.socket.send_to(&res, addr);
+
+        self.sessions.insert(
+            addr,
+            Session {
+                connection: Connection::new(addr, mtu),
+                protocol: None,
+                compression: false,
+                disconnected: None,
+            },
+        );
+    }
+
+    /// The server id string shown in the Bedrock server list
+    ///
+    /// `MCPE;line 1;protocol;version;online;max;guid;line 2;gamemode;gamemode id;port v4;port v6;`
+    fn motd<S: StatusProvider>(&self, status: &S) -> String {
+        // pings don't say anything about the client
+        let config = status.status(&StatusRequest::default());
+        let description = config
+            .bedrock_description
+            .clone()
+            .unwrap_or_else(|| config.legacy_description().to_legacy());
+        // `;` separates the fields, so it can't be used within them
+        let description = description.replace(';', ",");
+        let mut lines = description.lines();
+
+        let players = config.players.unwrap_or_default();
+        let version = config
+            .version_name
+            .unwrap_or_else(|| concat!(MIN_SUPPORTED_VERSION, "+").to_string());
+
+        format!(
+            "MCPE;{};{};{};{};{};{};{};Survival;1;{};{};",
+            lines.next().unwrap_or_default(),
+            match self.login {
+                true => Self::LOGIN_PROTOCOL,
+                false => Self::PROTOCOL,
+            },
+            version.replace(';', ","),
+            players.online,
+            players.max,
+            self.guid,
+            lines.next().unwrap_or_default(),
+            self.port,
+            self.port,
+        )
+    }
+}
+
+// https://minecraft.wiki/w/Bedrock_Edition_protocol
+impl Session {
+    const GAME_PACKET: u8 = 0xFE;
+
+    const LOGIN: u32 = 0x01;
+    const DISCONNECT: u32 = 0x05;
+    const NETWORK_SETTINGS: u32 = 0x8F;
+    const REQUEST_NETWORK_SETTINGS: u32 = 0xC1;
+
+    /// Batches start with the compression algorithm since 1.20.60
+    const V1_20_60: i32 = 649;
+    /// Disconnects have a reason since 1.20.40
+    const V1_20_40: i32 = 622;
+    /// Disconnects have a filtered message since 1.21.20
+    const V1_21_20: i32 = 712;
+
+    const DEFLATE: u8 = 0x00;
+    const NO_COMPRESSION: u8 = 0xFF;
+    /// A Login packet is about 10KB, its chain and skin data are the biggest part
+    const MAX_BATCH_LEN: usize = 4 * 1024 * 1024;
+
+    fn handle<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
+        &mut self,
+        socket: &UdpSocket,
+        mut packet: Bytes,
+        config: &ServerConfig<T, M, S>,
+        state: &ConnectionState,
+    ) -> Result<(), ServerError> {
+        ensure(&packet, 1)?;
+        match packet.get_u8() {
+            raknet::CONNECTION_REQUEST => self.connection.accept(socket, packet)?,
+            raknet::CONNECTED_PING => self.connection.pong(socket, packet)?,
+            // the client confirming the connection, nothing to answer
+            raknet::NEW_INCOMING_CONNECTION => (),
+            raknet::DISCONNECT_NOTIFICATION => {
+                self.disconnected.get_or_insert(Instant::now());
+            }
+            Self::GAME_PACKET if self.disconnected.is_none() => {
+                for mut packet in self.read_batch(packet)? {
+                    let id = get_var_u32(&mut packet)? & 0x3FF;
+                    self.handle_game(socket, id, packet, config, state)?;
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn handle_game<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
+        &mut self,
+        socket: &UdpSocket,
+        id: u32,
+        mut packet: Bytes,
+        config: &ServerConfig<T, M, S>,
+        state: &ConnectionState,
+    ) -> Result<(), ServerError> {
+        match id {
+            Self::REQUEST_NETWORK_SETTINGS => {
+                ensure(&packet, 4)?;
+                self.protocol = Some(packet.get_i32());
+
+                let mut res = BytesMut::new();
+                // compress everything
+                res.put_u16_le(1);
+                res.put_u16_le(Self::DEFLATE as u16);
+                // no client throttling
+                res.put_u8(0);
+                res.put_u8(0);
+                res.put_f32_le(0.0);
+                self.send_game(socket, Self::NETWORK_SETTINGS, &res);
+
+                self.compression = true;
+            }
+            Self::LOGIN => self.login(socket, packet, config, state)?,
+            // anything else comes after the login, which we never finish
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn login<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
+        &mut self,
+        socket: &UdpSocket,
+        mut packet: Bytes,
+        config: &ServerConfig<T, M, S>,
+        state: &ConnectionState,
+    ) -> Result<(), ServerError> {
+        ensure(&packet, 4)?;
+        let protocol = packet.get_i32();
+        let _len = get_var_u32(&mut packet)?;
+        let chain = get_string_u32_le(&mut packet)?;
+        let client_data = get_string_u32_le(&mut packet)?;
+
+        let (identity, client) = chain::verify(&chain, &client_data)?;
+
+        // the player typed in the address with its port, like `play.example.com:19132`
+        let host = client
+            .server_address
+            .as_deref()
+            .map(|addr| match addr.rsplit_once(':') {
+                Some((host, port)) if port.parse::<u16>().is_ok() => host,
+                _ => addr,
+            })
+            .map(normalize_host)
+            .unwrap_or_default();
+
+        let mut state = state.clone();
+        state.apply_vhost(&config.vhosts, &host);
+
+        let nonce = match &state.nonces {
+            Some(nonces) => match nonces.extract(&host) {
+                Some(nonce) => Some(nonce),
+                None => {
+                    let message = nonces.binding().unbound_message.to_legacy();
+                    self.disconnect(socket, protocol, &message);
+                    return Ok(());
+                }
+            },
+            None => None,
+        };
+
+        let player = Player {
+            uuid: identity.uuid,
+            username: identity.display_name,
+            vhost: state.vhost.clone(),
+            xuid: Some(identity.xuid),
+        };
+        let (token, reused) = issue_token(&state, &player, nonce.as_deref());
+
+        // bedrock locales are like `en_US`, java ones like `en_us`
+        let locale = client.locale.map(|l| l.to_lowercase());
+        let display = state.token.display(&token);
+        let msg = state.message.clone().create_message(&MessageContext {
+            token: &display,
+            raw_token: &token,
+            protocol_version: protocol,
+            locale: locale.as_deref(),
+            bedrock: true,
+        });
+        self.disconnect(socket, protocol, &msg.to_legacy());
+
+        complete_join(&state, player, token, reused, nonce);
+
+        Ok(())
+    }
+
+    /// Shows the message on the disconnect screen, the connection is closed a bit later
+    fn disconnect(&mut self, socket: &UdpSocket, protocol: i32, message: &str) {
+        let mut res = BytesMut::new();
+        if protocol >= Self::V1_20_40 {
+            // zigzag encoded reason, 0 is unknown
+            VarInt(0).write(&mut res);
+        }
+        // don't skip the disconnect screen
+        res.put_u8(0);
+        put_string(&mut res, message);
+        if protocol >= Self::V1_21_20 {
+            put_string(&mut res, message);
+        }
+
+        self.send_game(socket, Self::DISCONNECT, &res);
+        self.disconnected = Some(Instant::now());
+    }
+
+    /// Splits a batch into its packets, decompressing it first if needed
+    fn read_batch(&self, mut batch: Bytes) -> Result<Vec<Bytes>, BedrockLoginError> {
+        if self.compression {
+            let algorithm = match self.protocol.unwrap_or_default() >= Self::V1_20_60 {
+                true => {
+                    ensure(&batch, 1)?;
+                    batch.get_u8()
+                }
+                false => Self::DEFLATE,
+            };
+
+            batch = match algorithm {
+                Self::DEFLATE => inflate::decompress_to_vec_with_limit(&batch, Self::MAX_BATCH_LEN)
+                    .map_err(|_| BedrockLoginError::Malformed("compressed batch"))?
+                    .into(),
+                Self::NO_COMPRESSION => batch,
+                algorithm => return Err(BedrockLoginError::UnsupportedCompression(algorithm)),
+            };
+        }
+
+        let mut packets = Vec::new();
+        while batch.has_remaining() {
+            let len = get_var_u32(&mut batch)? as usize;
+            ensure(&batch, len)?;
+            packets.push(batch.split_to(len));
+        }
+
+        Ok(packets)
+    }
+
+    /// Sends a single packet in its own batch
+    fn send_game(&mut self, socket: &UdpSocket, id: u32, data: &[u8]) {
+        let mut packet = BytesMut::new();
+        VarInt(id as i32).write(&mut packet);
+        packet.extend_from_slice(data);
+
+        let mut batch = BytesMut::new();
+        VarInt(packet.len() as i32).write(&mut batch);
+        batch.extend_from_slice(&packet);
+
+        let mut res = BytesMut::new();
+        res.put_u8(Self::GAME_PACKET);
+        if self.compression {
+            if self.protocol.unwrap_or_default() >= Self::V1_20_60 {
+                res.put_u8(Self::DEFLATE);
+            }
+            res.extend_from_slice(&deflate::compress_to_vec(&batch, 4));
+        } else {
+            res.extend_from_slice(&batch);
+        }
+
+        self.connection.send(socket, &res);
+    }
+}
+
+/// Reads an unsigned varint, without panicking on a truncated packet like [`VarInt`] would
+fn get_var_u32(data: &mut Bytes) -> Result<u32, BedrockLoginError> {
+    let mut value = 0u32;
+    for position in (0..35).step_by(7) {
+        ensure(data, 1)?;
+        let byte = data.get_u8();
+        value |= ((byte & 0x7F) as u32) << position;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(BedrockLoginError::Malformed("varint"))
+}
+
+/// Reads a string with a little endian u32 length, as the Login packet has them
+fn get_string_u32_le(data: &mut Bytes) -> Result<String, BedrockLoginError> {
+    ensure(data, 4)?;
+    let len = data.get_u32_le() as usize;
+    ensure(data, len)?;
+
+    String::from_utf8(data.split_to(len).to_vec())
+        .map_err(|_| BedrockLoginError::Malformed("string"))
+}
+
+fn put_string(data: &mut BytesMut, value: &str) {
+    VarInt(value.len() as i32).write(data);
+    data.extend_from_slice(value.as_bytes());
+}
@@ -0,0 +1,556 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use chrono::Utc;
+
+use crate::error::BedrockLoginError;
+
+// https://minecraft.wiki/w/Bedrock_Edition_protocol/RakNet
+
+pub const OFFLINE_MESSAGE_ID: [u8; 16] = [
+    0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78,
+];
+pub const PROTOCOL: u8 = 11;
+
+pub const OPEN_CONNECTION_REQUEST_1: u8 = 0x05;
+pub const OPEN_CONNECTION_REPLY_1: u8 = 0x06;
+pub const OPEN_CONNECTION_REQUEST_2: u8 = 0x07;
+pub const OPEN_CONNECTION_REPLY_2: u8 = 0x08;
+pub const NO_FREE_INCOMING_CONNECTIONS: u8 = 0x14;
+pub const INCOMPATIBLE_PROTOCOL: u8 = 0x19;
+
+pub const CONNECTED_PING: u8 = 0x00;
+pub const CONNECTED_PONG: u8 = 0x03;
+pub const CONNECTION_REQUEST: u8 = 0x09;
+pub const CONNECTION_REQUEST_ACCEPTED: u8 = 0x10;
+pub const NEW_INCOMING_CONNECTION: u8 = 0x13;
+pub const DISCONNECT_NOTIFICATION: u8 = 0x15;
+
+const FRAME_SET: u8 = 0x80;
+const FRAME_SET_LAST: u8 = 0x8D;
+const NACK: u8 = 0xA0;
+const ACK: u8 = 0xC0;
+
+/// Reliable ordered, the only reliability we send with
+const RELIABLE_ORDERED: u8 = 3;
+const SPLIT_FLAG: u8 = 0x10;
+/// Datagram header, frame header with split info and some room for ip options
+const FRAME_OVERHEAD: usize = 60;
+/// A Login packet is around a dozen parts, anything far above that isn't a client
+const MAX_SPLIT_COUNT: u32 = 128;
+/// A client sends one packet at a time during the login, so only a few splits are ever unfinished
+const MAX_SPLITS: usize = 4;
+/// Reliable indexes this far below the newest one are dropped, the client doesn't resend that late
+const RELIABLE_WINDOW: u32 = 512;
+const RESEND_AFTER: Duration = Duration::from_millis(500);
+
+/// Reads the address a RakNet packet contains, only IPv4 is written but both can be read
+pub fn get_address(data: &mut Bytes) -> Result<SocketAddr, BedrockLoginError> {
+    ensure(data, 1)?;
+    match data.get_u8() {
+        4 => {
+            ensure(data, 6)?;
+            let ip = !data.get_u32();
+            Ok(SocketAddr::new(IpAddr::V4(ip.into()), data.get_u16()))
+        }
+        6 => {
+            // family, port, flow info, address and scope id
+            ensure(data, 2 + 2 + 4 + 16 + 4)?;
+            data.advance(2);
+            let port = data.get_u16();
+            data.advance(4);
+            let ip = data.get_u128();
+            data.advance(4);
+            Ok(SocketAddr::new(IpAddr::V6(ip.into()), port))
+        }
+        _ => Err(BedrockLoginError::Malformed("address")),
+    }
+}
+
+/// Writes an address, IPv4 bytes are inverted as RakNet does
+pub fn put_address(data: &mut BytesMut, addr: SocketAddr) {
+    match addr {
+        SocketAddr::V4(addr) => {
+            data.put_u8(4);
+            data.put_u32(!u32::from(*addr.ip()));
+            data.put_u16(addr.port());
+        }
+        SocketAddr::V6(addr) => {
+            data.put_u8(6);
+            // AF_INET6 as windows defines it, which is what the client expects
+            data.put_u16_le(23);
+            data.put_u16(addr.port());
+            data.put_u32(addr.flowinfo());
+            data.put_u128(u128::from(*addr.ip()));
+            data.put_u32(addr.scope_id());
+        }
+    }
+}
+
+pub fn ensure(data: &impl Buf, len: usize) -> Result<(), BedrockLoginError> {
+    match data.remaining() >= len {
+        true => Ok(()),
+        false => Err(BedrockLoginError::Malformed("packet too short")),
+    }
+}
+
+fn get_u24_le(data: &mut Bytes) -> u32 {
+    data.get_uint_le(3) as u32
+}
+
+fn put_u24_le(data: &mut BytesMut, value: u32) {
+    data.put_uint_le(value as u64, 3);
+}
+
+/// A split packet still waiting for its other parts
+#[derive(Debug)]
+struct Split {
+    parts: Vec<Option<Bytes>>,
+    received: usize,
+}
+
+/// The reliability layer of a connected client, frames are acknowledged, reassembled and resent
+///
+/// Only what a login needs is implemented, everything is sent reliable ordered on channel 0.
+#[derive(Debug)]
+pub struct Connection {
+    pub addr: SocketAddr,
+    pub mtu: u16,
+    pub last_seen: Instant,
+    received_reliable: HashSet<u32>,
+    /// Reliable indexes below this were received or are too old to matter
+    reliable_floor: u32,
+    splits: HashMap<u16, Split>,
+    acks: Vec<u32>,
+    send_sequence: u32,
+    send_reliable: u32,
+    send_ordered: u32,
+    send_split: u16,
+    /// Datagrams the client hasn't acknowledged yet, by sequence number
+    unacked: HashMap<u32, (Instant, Bytes)>,
+}
+
+impl Connection {
+    pub fn new(addr: SocketAddr, mtu: u16) -> Self {
+        Self {
+            addr,
+            mtu,
+            last_seen: Instant::now(),
+            received_reliable: HashSet::new(),
+            reliable_floor: 0,
+            splits: HashMap::new(),
+            acks: Vec::new(),
+            send_sequence: 0,
+            send_reliable: 0,
+            send_ordered: 0,
+            send_split: 0,
+            unacked: HashMap::new(),
+        }
+    }
+
+    /// Handles a datagram from the client, returning the packets it completed
+    pub fn receive(
+        &mut self,
+        socket: &UdpSocket,
+        mut data: Bytes,
+    ) -> Result<Vec<Bytes>, BedrockLoginError> {
+        self.last_seen = Instant::now();
+        ensure(&data, 1)?;
+
+        match data.get_u8() {
+            ACK => {
+                for sequence in Self::get_records(&mut data)? {
+                    self.unacked.remove(&sequence);
+                }
+                Ok(Vec::new())
+            }
+            NACK => {
+                for sequence in Self::get_records(&mut data)? {
+                    if let Some((sent, datagram)) = self.unacked.get_mut(&sequence) {
+                        *sent = Instant::now();
+                        let _ = socket.send_to(datagram, self.addr);
+                    }
+                }
+                Ok(Vec::new())
+            }
+            FRAME_SET..=FRAME_SET_LAST => {
+                ensure(&data, 3)?;
+                self.acks.push(get_u24_le(&mut data));
+
+                let mut packets = Vec::new();
+                while data.has_remaining() {
+                    if let Some(packet) = self.get_frame(&mut data)? {
+                        packets.push(packet);
+                    }
+                }
+                Ok(packets)
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Reads one frame, `None` if it was a duplicate or part of an unfinished split packet
+    fn get_frame(&mut self, data: &mut Bytes) -> Result<Option<Bytes>, BedrockLoginError> {
+        ensure(data, 3)?;
+        let flags = data.get_u8();
+        let reliability = flags >> 5;
+        let len = (data.get_u16() as usize).div_ceil(8);
+
+        let reliable = matches!(reliability, 2 | 3 | 4 | 6 | 7);
+        let sequenced = matches!(reliability, 1 | 4);
+        let ordered = matches!(reliability, 1 | 3 | 4 | 7);
+
+        let mut reliable_index = None;
+        if reliable {
+            ensure(data, 3)?;
+            reliable_index = Some(get_u24_le(data));
+        }
+        if sequenced {
+            ensure(data, 3)?;
+            data.advance(3);
+        }
+        if ordered {
+            // index and channel, a login waits for each response so it can't arrive out of order
+            ensure(data, 4)?;
+            data.advance(4);
+        }
+
+        let split = match flags & SPLIT_FLAG != 0 {
+            true => {
+                ensure(data, 10)?;
+                Some((data.get_u32(), data.get_u16(), data.get_u32()))
+            }
+            false => None,
+        };
+
+        ensure(data, len)?;
+        let body = data.split_to(len);
+
+        if let Some(index) = reliable_index
+            && !self.receive_reliable(index)
+        {
+            return Ok(None);
+        }
+
+        match split {
+            Some((count, id, index)) => self.reassemble(body, count, id, index),
+            None => Ok(Some(body)),
+        }
+    }
+
+    /// Remembers a reliable index, `false` if it was received before
+    fn receive_reliable(&mut self, index: u32) -> bool {
+        if index < self.reliable_floor || !self.received_reliable.insert(index) {
+            return false;
+        }
+
+        if let Some(floor) = index.checked_sub(RELIABLE_WINDOW)
+            && floor > self.reliable_floor
+        {
+            self.reliable_floor = floor;
+            self.received_reliable.retain(|&i| i >= floor);
+        }
+
+        true
+    }
+
+    fn reassemble(
+        &mut self,
+        body: Bytes,
+        count: u32,
+        id: u16,
+        index: u32,
+    ) -> Result<Option<Bytes>, BedrockLoginError> {
+        if count == 0 || count > MAX_SPLIT_COUNT || index >= count {
+            return Err(BedrockLoginError::Malformed("split packet"));
+        }
+
+        if !self.splits.contains_key(&id) && self.splits.len() >= MAX_SPLITS {
+            return Err(BedrockLoginError::Malformed("too many split packets"));
+        }
+
+        let split = self.splits.entry(id).or_insert_with(|| Split {
+            parts: vec![None; count as usize],
+            received: 0,
+        });
+        if split.parts.len() != count as usize {
+            return Err(BedrockLoginError::Malformed("split packet"));
+        }
+        if split.parts[index as usize].replace(body).is_none() {
+            split.received += 1;
+        }
+        if split.received < split.parts.len() {
+            return Ok(None);
+        }
+
+        let parts = std::mem::take(&mut split.parts);
+        self.splits.remove(&id);
+        let mut packet = BytesMut::new();
+        for part in parts.into_iter().flatten() {
+            packet.extend_from_slice(&part);
+        }
+
+        Ok(Some(packet.freeze()))
+    }
+
+    /// Reads the sequence numbers of an ACK or NACK
+    fn get_records(data: &mut Bytes) -> Result<Vec<u32>, BedrockLoginError> {
+        ensure(data, 2)?;
+        let count = data.get_u16();
+
+        let mut sequences = Vec::new();
+        for _ in 0..count {
+            ensure(data, 4)?;
+            let single = data.get_u8() == 1;
+            let start = get_u24_le(data);
+            let end = match single {
+                true => start,
+                false => {
+                    ensure(data, 3)?;
+                    get_u24_le(data)
+                }
+            };
+
+            // a client never has more than a handful of datagrams to acknowledge from us
+            sequences.extend((start..=end).take(MAX_SPLIT_COUNT as usize));
+        }
+
+        Ok(sequences)
+    }
+
+    /// Sends a packet reliable ordered, split into parts if it doesn't fit in one datagram
+    pub fn send(&mut self, socket: &UdpSocket, packet: &[u8]) {
+        let max_len = self.mtu as usize - FRAME_OVERHEAD;
+        let ordered_index = self.send_ordered;
+        self.send_ordered += 1;
+
+        let parts: Vec<&[u8]> = packet.chunks(max_len).collect();
+        let split_id = self.send_split;
+        if parts.len() > 1 {
+            self.send_split = self.send_split.wrapping_add(1);
+        }
+
+        for (index, part) in parts.iter().enumerate() {
+            let mut frame = BytesMut::new();
+            let split = parts.len() > 1;
+
+            frame.put_u8(RELIABLE_ORDERED << 5 | if split { SPLIT_FLAG } else { 0 });
+            frame.put_u16((part.len() * 8) as u16);
+            put_u24_le(&mut frame, self.send_reliable);
+            self.send_reliable += 1;
+            put_u24_le(&mut frame, ordered_index);
+            frame.put_u8(0);
+            if split {
+                frame.put_u32(parts.len() as u32);
+                frame.put_u16(split_id);
+                frame.put_u32(index as u32);
+            }
+            frame.extend_from_slice(part);
+
+            self.send_datagram(socket, &frame);
+        }
+    }
+
+    fn send_datagram(&mut self, socket: &UdpSocket, frame: &[u8]) {
+        let mut datagram = BytesMut::new();
+        // valid datagram, continuous send
+        datagram.put_u8(FRAME_SET | 0x04);
+        put_u24_le(&mut datagram, self.send_sequence);
+        datagram.extend_from_slice(frame);
+        let datagram = datagram.freeze();
+
+        let _ = socket.send_to(&datagram, self.addr);
+        self.unacked
+            .insert(self.send_sequence, (Instant::now(), datagram));
+        self.send_sequence += 1;
+    }
+
+    /// Acknowledges what was received since the last call, and resends what was never acknowledged
+    pub fn flush(&mut self, socket: &UdpSocket) {
+        if !self.acks.is_empty() {
+            let mut ack = BytesMut::new();
+            ack.put_u8(ACK);
+            ack.put_u16(self.acks.len() as u16);
+            for sequence in self.acks.drain(..) {
+                ack.put_u8(1);
+                put_u24_le(&mut ack, sequence);
+            }
+            let _ = socket.send_to(&ack, self.addr);
+        }
+
+        for (sent, datagram) in self.unacked.values_mut() {
+            if sent.elapsed() >= RESEND_AFTER {
+                *sent = Instant::now();
+                let _ = socket.send_to(datagram, self.addr);
+            }
+        }
+    }
+
+    /// Accepts the connection request, which is the first packet sent over the connection
+    pub fn accept(
+        &mut self,
+        socket: &UdpSocket,
+        mut request: Bytes,
+    ) -> Result<(), BedrockLoginError> {
+        // client guid, request time and whether security is used
+        ensure(&request, 8 + 8 + 1)?;
+        request.advance(8);
+        let time = request.get_i64();
+
+        let mut res = BytesMut::new();
+        res.put_u8(CONNECTION_REQUEST_ACCEPTED);
+        put_address(&mut res, self.addr);
+        res.put_u16(0);
+        for _ in 0..20 {
+            put_address(
+                &mut res,
+                SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)),
+            );
+        }
+        res.put_i64(time);
+        res.put_i64(Utc::now().timestamp_millis());
+
+        self.send(socket, &res);
+        Ok(())
+    }
+
+    /// Answers a ping the client sends to keep the connection alive
+    pub fn pong(&mut self, socket: &UdpSocket, mut ping: Bytes) -> Result<(), BedrockLoginError> {
+        ensure(&ping, 8)?;
+        let time = ping.get_i64();
+
+        let mut res = BytesMut::new();
+        res.put_u8(CONNECTED_PONG);
+        res.put_i64(time);
+        res.put_i64(Utc::now().timestamp_millis());
+
+        self.send(socket, &res);
+        Ok(())
+    }
+
+    /// Tells the client the connection is closed, it isn't waited for
+    pub fn disconnect(&mut self, socket: &UdpSocket) {
+        self.send(socket, &[DISCONNECT_NOTIFICATION]);
+        self.flush(socket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELIABLE: u8 = 2;
+
+    fn connection() -> Connection {
+        Connection::new("127.0.0.1:19132".parse().unwrap(), 1400)
+    }
+
+    fn frame(reliable_index: u32, split: Option<(u32, u16, u32)>, body: &[u8]) -> Bytes {
+        let mut frame = BytesMut::new();
+        frame.put_u8(RELIABLE << 5 | if split.is_some() { SPLIT_FLAG } else { 0 });
+        frame.put_u16((body.len() * 8) as u16);
+        put_u24_le(&mut frame, reliable_index);
+        if let Some((count, id, index)) = split {
+            frame.put_u32(count);
+            frame.put_u16(id);
+            frame.put_u32(index);
+        }
+        frame.extend_from_slice(body);
+        frame.freeze()
+    }
+
+    #[test]
+    fn address_round_trip() {
+        for addr in ["192.168.1.20:19132", "[2001:db8::1]:19133"] {
+            let addr: SocketAddr = addr.parse().unwrap();
+            let mut data = BytesMut::new();
+            put_address(&mut data, addr);
+
+            assert_eq!(get_address(&mut data.freeze()).unwrap(), addr);
+        }
+    }
+
+    #[test]
+    fn duplicate_frames() {
+        let mut connection = connection();
+
+        let packet = connection.get_frame(&mut frame(0, None, b"login")).unwrap();
+        assert_eq!(packet.as_deref(), Some(&b"login"[..]));
+        assert!(
+            connection
+                .get_frame(&mut frame(0, None, b"login"))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn reliable_window() {
+        let mut connection = connection();
+        for index in 0..RELIABLE_WINDOW * 3 {
+            connection.get_frame(&mut frame(index, None, b"x")).unwrap();
+        }
+
+        assert!(connection.received_reliable.len() <= RELIABLE_WINDOW as usize + 1);
+        // evicted indexes still count as received
+        assert!(
+            connection
+                .get_frame(&mut frame(0, None, b"x"))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn reassemble_out_of_order() {
+        let mut connection = connection();
+
+        let parts = [(2, b"ld"), (0, b"he"), (1, b"wo")];
+        let mut packet = None;
+        for (reliable_index, (index, part)) in parts.into_iter().enumerate() {
+            packet = connection
+                .get_frame(&mut frame(reliable_index as u32, Some((3, 7, index)), part))
+                .unwrap();
+        }
+
+        assert_eq!(packet.as_deref(), Some(&b"hewold"[..]));
+        assert!(connection.splits.is_empty());
+    }
+
+    #[test]
+    fn too_many_splits() {
+        let mut connection = connection();
+        for id in 0..MAX_SPLITS as u16 {
+            let part = frame(id as u32, Some((2, id, 0)), b"x");
+            assert!(connection.get_frame(&mut part.clone()).unwrap().is_none());
+        }
+
+        let part = frame(MAX_SPLITS as u32, Some((2, MAX_SPLITS as u16, 0)), b"x");
+        assert!(connection.get_frame(&mut part.clone()).is_err());
+    }
+
+    #[test]
+    fn invalid_split() {
+        let mut connection = connection();
+
+        assert!(
+            connection
+                .get_frame(&mut frame(0, Some((0, 1, 0)), b"x"))
+                .is_err()
+        );
+        assert!(
+            connection
+                .get_frame(&mut frame(1, Some((2, 1, 2)), b"x"))
+                .is_err()
+        );
+        assert!(
+            connection
+                .get_frame(&mut frame(2, Some((MAX_SPLIT_COUNT + 1, 1, 0)), b"x"))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,84 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    error::TypeError,
+    minecraft::{
+        array::Array,
+        packet::{ReadPacketData, WritePacketData},
+    },
+};
+
+/// Bits packed into longs, prefixed with the number of longs
+///
+/// https://minecraft.wiki/w/Java_Edition_protocol/Data_types#BitSet
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(not(test), allow(dead_code))] // no packet we handle has one yet
+pub(crate) struct BitSet(pub Vec<i64>);
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl BitSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        self.0
+            .get(index / 64)
+            .is_some_and(|long| long & (1 << (index % 64)) != 0)
+    }
+
+    /// Grows the set if needed
+    pub fn set(&mut self, index: usize, value: bool) {
+        if index / 64 >= self.0.len() {
+            if !value {
+                return;
+            }
+            self.0.resize(index / 64 + 1, 0);
+        }
+
+        let long = &mut self.0[index / 64];
+        match value {
+            true => *long |= 1 << (index % 64),
+            false => *long &= !(1 << (index % 64)),
+        }
+    }
+}
+
+impl ReadPacketData for BitSet {
+    fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+        Ok(Self(Array::<i64>::read(data)?.into_inner()))
+    }
+}
+
+impl WritePacketData for BitSet {
+    fn write(self, data: &mut BytesMut) {
+        Array::new(self.0).write(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use crate::minecraft::packet::{ReadPacketData, WritePacketData};
+
+    use super::BitSet;
+
+    #[test]
+    fn fixture() {
+        let mut set = BitSet::new();
+        set.set(0, true);
+        set.set(65, true);
+        set.set(200, false);
+
+        assert!(set.get(0) && set.get(65));
+        assert!(!set.get(1) && !set.get(200));
+
+        let bytes = b"\x02\x00\x00\x00\x00\x00\x00\x00\x01\x00\x00\x00\x00\x00\x00\x00\x02";
+        let mut data = BytesMut::new();
+        set.clone().write(&mut data);
+        assert_eq!(&data[..], bytes);
+
+        assert_eq!(BitSet::read(&mut Bytes::from_static(bytes)).unwrap(), set);
+    }
+}
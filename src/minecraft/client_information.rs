@@ -0,0 +1,15 @@
+use mc_headless_auth_derive::Packet;
+
+use crate::minecraft::string::PacketString;
+
+/// Sent by the client in the configuration phase, only the locale is of interest to us
+///
+/// https://minecraft.wiki/w/Java_Edition_protocol/Packets#Client_Information_(configuration)
+///
+/// Everything after the locale is view distance, chat settings etc. which we don't need, so it isn't read.
+#[derive(Debug, Clone, Packet)]
+#[packet(id = 0x00, state = Configuration)]
+pub struct ClientInformation {
+    /// e.g. `en_us`
+    pub locale: PacketString<16>,
+}
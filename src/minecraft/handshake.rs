@@ -1,14 +1,21 @@
-use bytes::{Buf, Bytes};
+use bytes::{Bytes, BytesMut};
+use mc_headless_auth_derive::Packet;
 
 use crate::{
     error::TypeError,
-    minecraft::{packet::ReadPacketData, string::PacketString, var_int::VarInt},
+    minecraft::{
+        packet::{ReadPacketData, WritePacketData},
+        string::PacketString,
+        var_int::VarInt,
+    },
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Packet)]
+#[packet(id = 0x00, state = Handshake)]
 #[allow(unused)]
 pub struct Handshake {
     pub protocol_version: VarInt,
+    /// Vanilla allows 255 characters, but proxies forwarding the players ip append more
     pub server_address: PacketString,
     pub server_port: u16,
     pub intent: Intent,
@@ -23,6 +30,23 @@ pub enum Intent {
     Unknown(i32),
 }
 
+impl Handshake {
+    /// The hostname the client connected with, lowercased and without anything a client might append
+    pub fn hostname(&self) -> String {
+        normalize_host(&self.server_address.0)
+    }
+}
+
+/// Lowercases a hostname and strips anything a client might append
+pub fn normalize_host(host: &str) -> String {
+    // modded clients append extra data after a null byte, and fqdns may end with a dot
+    host.split('\0')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('.')
+        .to_lowercase()
+}
+
 impl Intent {
     fn from_i32(val: i32) -> Intent {
         match val {
@@ -32,20 +56,76 @@ impl Intent {
             val => Intent::Unknown(val),
         }
     }
+
+    fn to_i32(&self) -> i32 {
+        match self {
+            Intent::Status => 1,
+            Intent::Login => 2,
+            Intent::Transfer => 3,
+            Intent::Unknown(val) => *val,
+        }
+    }
 }
 
-impl ReadPacketData for Handshake {
+impl ReadPacketData for Intent {
     fn read(data: &mut Bytes) -> Result<Self, TypeError> {
-        let p_version = VarInt::read(data)?;
-        let s_addr = PacketString::read(data)?;
-        let s_port = data.get_u16();
-        let intent = VarInt::read(data)?;
-
-        Ok(Handshake {
-            protocol_version: p_version,
-            server_address: s_addr,
-            server_port: s_port,
-            intent: Intent::from_i32(intent.0),
-        })
+        Ok(Intent::from_i32(VarInt::read(data)?.0))
+    }
+}
+
+impl WritePacketData for Intent {
+    fn write(self, data: &mut BytesMut) {
+        VarInt(self.to_i32()).write(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use crate::minecraft::{
+        packet::{ReadPacketData, WritePacketData},
+        protocol_version,
+        string::PacketString,
+        var_int::VarInt,
+    };
+
+    use super::{Handshake, Intent};
+
+    /// A 1.21.5 client logging in to `localhost:25565`
+    const LOGIN: &[u8] = b"\x82\x06\x09localhost\x63\xDD\x02";
+
+    #[test]
+    fn read() {
+        let handshake = Handshake::read(&mut Bytes::from_static(LOGIN)).unwrap();
+
+        assert_eq!(
+            handshake.protocol_version,
+            VarInt(protocol_version::V1_21_5)
+        );
+        assert_eq!(handshake.server_address.0, "localhost");
+        assert_eq!(handshake.server_port, 25565);
+        assert!(matches!(handshake.intent, Intent::Login));
+    }
+
+    #[test]
+    fn write() {
+        let mut data = BytesMut::new();
+        Handshake {
+            protocol_version: VarInt(protocol_version::V1_21_5),
+            server_address: PacketString::new("localhost"),
+            server_port: 25565,
+            intent: Intent::Login,
+        }
+        .write(&mut data);
+
+        assert_eq!(&data[..], LOGIN);
+    }
+
+    #[test]
+    fn unknown_intent() {
+        let handshake =
+            Handshake::read(&mut Bytes::from_static(b"\x82\x06\x00\x63\xDD\x07")).unwrap();
+        assert!(matches!(handshake.intent, Intent::Unknown(7)));
     }
 }
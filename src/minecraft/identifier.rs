@@ -0,0 +1,118 @@
+use std::fmt::{self, Display};
+
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    error::TypeError,
+    minecraft::{
+        packet::{ReadPacketData, WritePacketData},
+        string::PacketString,
+    },
+};
+
+/// A namespaced location like `minecraft:stone`, the namespace defaults to `minecraft`
+///
+/// https://minecraft.wiki/w/Resource_location
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(not(test), allow(dead_code))] // no packet we handle has one yet
+pub(crate) struct Identifier {
+    pub namespace: String,
+    pub path: String,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Identifier {
+    pub const DEFAULT_NAMESPACE: &str = "minecraft";
+
+    pub fn new(namespace: impl Into<String>, path: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            path: path.into(),
+        }
+    }
+
+    /// Parses `namespace:path` or just `path`, with the characters vanilla allows in each
+    pub fn parse(value: &str) -> Result<Self, TypeError> {
+        let (namespace, path) = value
+            .split_once(':')
+            .unwrap_or((Self::DEFAULT_NAMESPACE, value));
+        let namespace = match namespace {
+            "" => Self::DEFAULT_NAMESPACE,
+            namespace => namespace,
+        };
+
+        let valid = |c: char| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.');
+        if !namespace.chars().all(valid) || !path.chars().all(|c| valid(c) || c == '/') {
+            return Err(TypeError::InvalidIdentifier(value.to_string()));
+        }
+
+        Ok(Self::new(namespace, path))
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.path)
+    }
+}
+
+impl ReadPacketData for Identifier {
+    fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+        let value: PacketString = PacketString::read(data)?;
+        Self::parse(&value.0)
+    }
+}
+
+impl WritePacketData for Identifier {
+    fn write(self, data: &mut BytesMut) {
+        PacketString::new(self).write(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use crate::{
+        error::TypeError,
+        minecraft::packet::{ReadPacketData, WritePacketData},
+    };
+
+    use super::Identifier;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Identifier::parse("stone").unwrap(),
+            Identifier::new("minecraft", "stone")
+        );
+        assert_eq!(
+            Identifier::parse(":stone").unwrap(),
+            Identifier::new("minecraft", "stone")
+        );
+        assert_eq!(
+            Identifier::parse("mc-ha:textures/block.png").unwrap(),
+            Identifier::new("mc-ha", "textures/block.png")
+        );
+
+        for invalid in ["Stone", "a/b:c", "a:b:c", "a:b c"] {
+            assert!(matches!(
+                Identifier::parse(invalid),
+                Err(TypeError::InvalidIdentifier(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn fixture() {
+        let mut data = BytesMut::new();
+        Identifier::new("minecraft", "brand").write(&mut data);
+        assert_eq!(&data[..], b"\x0Fminecraft:brand");
+
+        let mut data = Bytes::from_static(b"\x05brand");
+        assert_eq!(
+            Identifier::read(&mut data).unwrap(),
+            Identifier::new("minecraft", "brand")
+        );
+    }
+}
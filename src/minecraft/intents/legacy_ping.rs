@@ -1,59 +1,159 @@
-use crate::minecraft::packet::InitPacket;
+use std::net::TcpStream;
 
-pub fn compare_init_bytes(bytes: [u8; 3]) -> Option<InitPacket> {
-    match bytes {
+use bytes::{BufMut, BytesMut};
+
+use crate::{
+    error::TypeError,
+    minecraft::{intents::legacy_ping::_1dot6::PingHost, packet::InitPacket},
+};
+
+const KICK_PACKET_ID: u8 = 0xFF;
+
+/// Matches the first bytes against the legacy pings, a 1.6 ping is read completely
+pub fn compare_init_bytes(
+    bytes: [u8; 3],
+    stream: &mut TcpStream,
+) -> Result<Option<InitPacket>, TypeError> {
+    Ok(match bytes {
         [254, 0, 0] => Some(InitPacket::Vbeta1_8To1_3),
         [254, 1, 0] => Some(InitPacket::V1_4To1_5),
-        [254, 1, 250] => Some(InitPacket::V1_6),
+        [254, 1, 250] => Some(InitPacket::V1_6(PingHost::read_stream(stream)?)),
         _ => None,
+    })
+}
+
+/// Legacy pings are answered with a kick packet, its reason is a UTF-16BE string  
+/// prefixed with its length in UTF-16 code units, not in bytes.  
+fn kick(reason: &str) -> BytesMut {
+    // anything longer couldn't be prefixed with its length
+    let units: Vec<u16> = reason.encode_utf16().take(u16::MAX as usize).collect();
+
+    let mut data = BytesMut::with_capacity(1 + 2 + units.len() * 2);
+    data.put_u8(KICK_PACKET_ID);
+    data.put_u16(units.len() as u16);
+    for unit in units {
+        data.put_u16(unit);
     }
+
+    data
 }
 
 pub mod _1dot6 {
-    use std::{io::Write, net::TcpStream};
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+    };
 
-    use bytes::{BufMut, BytesMut};
+    use bytes::{Buf, Bytes, BytesMut};
     use constcat::concat;
 
     use crate::{
         config::{MIN_SUPPORTED_VERSION, StatusConfig},
         error::TypeError,
+        minecraft::intents::legacy_ping::kick,
     };
 
-    /// https://minecraft.wiki/w/Java_Edition_protocol/Server_List_Ping#1.6
-    pub fn advance(stream: &mut TcpStream, config: StatusConfig) -> Result<(), TypeError> {
-        let mut data = BytesMut::new();
+    /// Never a real protocol, so 1.4 and 1.5 clients that don't send theirs show the version name
+    const UNKNOWN_PROTOCOL: i32 = 127;
+    const PING_HOST_CHANNEL: &str = "MC|PingHost";
 
-        data.put_u8(0xFF);
+    /// The `MC|PingHost` plugin message 1.6 clients send after the ping
+    #[derive(Debug, Clone)]
+    pub struct PingHost {
+        pub protocol_version: i32,
+        /// The address the client used to connect, as it was typed in
+        pub hostname: String,
+        pub port: u16,
+    }
 
-        let str = format!(
-            "§1\0{}\0{}\0{}\0{}\0{}",
-            "127",
-            concat!(MIN_SUPPORTED_VERSION, "+"),
-            config.legacy_decription.unwrap_or_default(),
-            "0",
-            "0"
-        );
+    impl PingHost {
+        /// Reads the plugin message that follows the `0xFE 0x01 0xFA` the ping was detected by
+        pub fn read_stream(stream: &mut impl Read) -> Result<Self, TypeError> {
+            let channel_len = read_u16(stream)? as usize;
+            let channel = read_utf16(&mut read_bytes(stream, channel_len * 2)?)?;
+            if channel != PING_HOST_CHANNEL {
+                return Err(TypeError::InvalidLegacyPing("unknown plugin channel"));
+            }
 
-        data.extend_from_slice(&(str.len() as u16 - 1).to_be_bytes()[..]);
+            let len = read_u16(stream)? as usize;
+            let mut data = read_bytes(stream, len)?;
 
-        // https://github.com/Duckulus/mc-honeypot/blob/1514807e8af7f7cbfd13111fec334b9f4883b605/src/server/legacy.rs
-        let utf16_be: Vec<u16> = str
-            .encode_utf16()
-            .collect::<Vec<u16>>()
-            .iter()
-            .map(|n| u16::from_be_bytes([(n & 0xFF) as u8, (n >> 8) as u8]))
-            .collect();
+            // protocol version, hostname length and port
+            if data.remaining() < 1 + 2 + 4 {
+                return Err(TypeError::InvalidLegacyPing("ping host is too short"));
+            }
+            let protocol_version = data.get_u8() as i32;
+            let hostname_len = data.get_u16() as usize;
+            if data.remaining() != hostname_len * 2 + 4 {
+                return Err(TypeError::InvalidLegacyPing(
+                    "hostname length does not match",
+                ));
+            }
+            let hostname = read_utf16(&mut data.split_to(hostname_len * 2))?;
+            let port = data.get_i32();
 
-        unsafe {
-            data.extend_from_slice(utf16_be.align_to::<u8>().1);
+            Ok(Self {
+                protocol_version,
+                hostname,
+                port: port as u16,
+            })
         }
+    }
 
+    fn read_u16(stream: &mut impl Read) -> Result<u16, TypeError> {
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).map_err(TypeError::ReadError)?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn read_bytes(stream: &mut impl Read, len: usize) -> Result<Bytes, TypeError> {
+        let mut buf = vec![0u8; len];
+        stream.read_exact(&mut buf).map_err(TypeError::ReadError)?;
+        Ok(Bytes::from_owner(buf))
+    }
+
+    fn read_utf16(data: &mut Bytes) -> Result<String, TypeError> {
+        let mut units = Vec::with_capacity(data.len() / 2);
+        while data.remaining() >= 2 {
+            units.push(data.get_u16());
+        }
+
+        String::from_utf16(&units).map_err(|_| TypeError::InvalidLegacyPing("invalid UTF-16"))
+    }
+
+    /// https://minecraft.wiki/w/Java_Edition_protocol/Server_List_Ping#1.6
+    ///
+    /// Responds with [`StatusConfig::protocol`] if set, otherwise the clients own protocol.  
+    pub fn advance(
+        stream: &mut TcpStream,
+        config: StatusConfig,
+        ping: Option<&PingHost>,
+    ) -> Result<(), TypeError> {
         stream
-            .write_all(&data)
-            .map_err(|e| TypeError::WriteError(e))?;
+            .write_all(&encode(&config, ping))
+            .map_err(TypeError::WriteError)
+    }
+
+    /// `§1\0protocol\0version\0motd\0online\0max`
+    pub fn encode(config: &StatusConfig, ping: Option<&PingHost>) -> BytesMut {
+        let protocol = config
+            .protocol
+            .or(ping.map(|p| p.protocol_version))
+            .unwrap_or(UNKNOWN_PROTOCOL);
+        let players = config.players.clone().unwrap_or_default();
+        let str = format!(
+            "§1\0{}\0{}\0{}\0{}\0{}",
+            protocol,
+            config
+                .version_name
+                .as_deref()
+                .unwrap_or(concat!(MIN_SUPPORTED_VERSION, "+")),
+            config.legacy_description().to_legacy(),
+            players.online,
+            players.max
+        );
 
-        Ok(())
+        kick(&str)
     }
 }
 
@@ -62,29 +162,40 @@ pub mod _1dot4_to_1dot5 {
 
     use crate::{config::StatusConfig, error::TypeError, minecraft::intents::legacy_ping::_1dot6};
 
+    /// 1.4 and 1.5 get the same response as 1.6, without saying what they were pinging
     pub fn advance(stream: &mut TcpStream, config: StatusConfig) -> Result<(), TypeError> {
-        _1dot6::advance(stream, config)
+        _1dot6::advance(stream, config, None)
     }
 }
 
 pub mod beta1dot8_to_1dot3 {
     use std::{io::Write, net::TcpStream};
 
-    use bytes::{BufMut, BytesMut};
+    use bytes::BytesMut;
 
-    use crate::{config::StatusConfig, error::TypeError};
+    use crate::{config::StatusConfig, error::TypeError, minecraft::intents::legacy_ping::kick};
 
     const MAX_PACKET_SIZE: u16 = 256;
 
     /// https://minecraft.wiki/w/Java_Edition_protocol/Server_List_Ping#Beta_1.8_to_1.3
     pub fn advance(stream: &mut TcpStream, config: StatusConfig) -> Result<(), TypeError> {
-        let mut data = BytesMut::new();
-        data.put_u8(0x0FF); // packet id
+        stream
+            .write_all(&encode(&config)?)
+            .map_err(TypeError::WriteError)
+    }
 
-        let str = format!("{}§0§0", config.legacy_decription.unwrap_or_default());
-        let str = str.encode_utf16();
+    /// `motd§online§max`
+    pub fn encode(config: &StatusConfig) -> Result<BytesMut, TypeError> {
+        // `§` separates the fields, so no formatting codes can be used
+        let players = config.players.clone().unwrap_or_default();
+        let str = format!(
+            "{}§{}§{}",
+            config.legacy_description().to_plain().replace('§', ""),
+            players.online,
+            players.max
+        );
 
-        let packet_len = str.clone().count() as u16;
+        let packet_len = u16::try_from(str.encode_utf16().count()).unwrap_or(u16::MAX);
         if packet_len > MAX_PACKET_SIZE {
             return Err(TypeError::BetaLegacyPacketIsTooBig(
                 MAX_PACKET_SIZE,
@@ -92,16 +203,169 @@ pub mod beta1dot8_to_1dot3 {
             ));
         }
 
-        data.put_u16(packet_len);
+        Ok(kick(&str))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::StatusConfig,
+        error::TypeError,
+        minecraft::intents::legacy_ping::{_1dot6, beta1dot8_to_1dot3},
+        status::StatusPlayers,
+        text::TextComponent,
+    };
+
+    use super::_1dot6::PingHost;
 
-        for unit in str.collect::<Vec<u16>>() {
-            data.extend_from_slice(&unit.to_be_bytes()[..]);
+    fn config(legacy_description: &str) -> StatusConfig {
+        StatusConfig {
+            legacy_decription: Some(legacy_description.to_string()),
+            version_name: Some("1.6.4".to_string()),
+            players: Some(StatusPlayers {
+                online: 1,
+                max: 20,
+                sample: Vec::new(),
+            }),
+            ..Default::default()
         }
+    }
 
-        stream
-            .write_all(&data)
-            .map_err(|e| TypeError::WriteError(e))?;
+    fn ping_host(protocol_version: i32) -> PingHost {
+        PingHost {
+            protocol_version,
+            hostname: "localhost".to_string(),
+            port: 25565,
+        }
+    }
+
+    #[test]
+    fn v1_6_non_ascii() {
+        let data = _1dot6::encode(&config("§aHé😀"), Some(&ping_host(74)));
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0xFF, 0x00, 0x17,
+            0x00, 0xA7, 0x00, 0x31, 0x00, 0x00,
+            0x00, 0x37, 0x00, 0x34, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x2E, 0x00, 0x36, 0x00, 0x2E, 0x00, 0x34, 0x00, 0x00,
+            0x00, 0xA7, 0x00, 0x61, 0x00, 0x48, 0x00, 0xE9, 0xD8, 0x3D, 0xDE, 0x00, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x00,
+            0x00, 0x32, 0x00, 0x30,
+        ];
+        assert_eq!(&data[..], expected);
+    }
+
+    #[test]
+    fn v1_6_configured_protocol() {
+        let config = StatusConfig {
+            protocol: Some(5),
+            ..config("Hi")
+        };
+        let data = _1dot6::encode(&config, Some(&ping_host(74)));
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0xFF, 0x00, 0x14,
+            0x00, 0xA7, 0x00, 0x31, 0x00, 0x00,
+            0x00, 0x35, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x2E, 0x00, 0x36, 0x00, 0x2E, 0x00, 0x34, 0x00, 0x00,
+            0x00, 0xA7, 0x00, 0x72, 0x00, 0x48, 0x00, 0x69, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x00,
+            0x00, 0x32, 0x00, 0x30,
+        ];
+        assert_eq!(&data[..], expected);
+    }
+
+    #[test]
+    fn v1_4_to_1_5_unknown_protocol() {
+        let data = _1dot6::encode(&config("Hé"), None);
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0xFF, 0x00, 0x16,
+            0x00, 0xA7, 0x00, 0x31, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x32, 0x00, 0x37, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x2E, 0x00, 0x36, 0x00, 0x2E, 0x00, 0x34, 0x00, 0x00,
+            // unstyled text is reset, so it doesn't inherit anything
+            0x00, 0xA7, 0x00, 0x72, 0x00, 0x48, 0x00, 0xE9, 0x00, 0x00,
+            0x00, 0x31, 0x00, 0x00,
+            0x00, 0x32, 0x00, 0x30,
+        ];
+        assert_eq!(&data[..], expected);
+    }
+
+    #[test]
+    fn beta_non_ascii() {
+        let data = beta1dot8_to_1dot3::encode(&config("§aHé😀")).unwrap();
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0xFF, 0x00, 0x09,
+            0x00, 0x48, 0x00, 0xE9, 0xD8, 0x3D, 0xDE, 0x00,
+            0x00, 0xA7, 0x00, 0x31,
+            0x00, 0xA7, 0x00, 0x32, 0x00, 0x30,
+        ];
+        assert_eq!(&data[..], expected);
+    }
+
+    #[test]
+    fn beta_strips_section_signs() {
+        let config = StatusConfig {
+            legacy_decription: None,
+            description: Some(TextComponent::text("a§b")),
+            ..config("")
+        };
+        let data = beta1dot8_to_1dot3::encode(&config).unwrap();
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0xFF, 0x00, 0x07,
+            0x00, 0x61, 0x00, 0x62,
+            0x00, 0xA7, 0x00, 0x31,
+            0x00, 0xA7, 0x00, 0x32, 0x00, 0x30,
+        ];
+        assert_eq!(&data[..], expected);
+    }
+
+    #[test]
+    fn beta_too_long() {
+        let result = beta1dot8_to_1dot3::encode(&config(&"a".repeat(300)));
+        assert!(matches!(
+            result,
+            Err(TypeError::BetaLegacyPacketIsTooBig(256, 305))
+        ));
+    }
+
+    #[test]
+    fn read_ping_host() {
+        // what follows `0xFE 0x01 0xFA` in a 1.6.4 ping of localhost:25570
+        #[rustfmt::skip]
+        let data: &[u8] = &[
+            0x00, 0x0B,
+            0x00, 0x4D, 0x00, 0x43, 0x00, 0x7C, 0x00, 0x50, 0x00, 0x69, 0x00, 0x6E, 0x00, 0x67,
+            0x00, 0x48, 0x00, 0x6F, 0x00, 0x73, 0x00, 0x74,
+            0x00, 0x19,
+            0x4E,
+            0x00, 0x09,
+            0x00, 0x6C, 0x00, 0x6F, 0x00, 0x63, 0x00, 0x61, 0x00, 0x6C, 0x00, 0x68, 0x00, 0x6F,
+            0x00, 0x73, 0x00, 0x74,
+            0x00, 0x00, 0x63, 0xE2,
+        ];
+        let ping = PingHost::read_stream(&mut &data[..]).unwrap();
+
+        assert_eq!(ping.protocol_version, 78);
+        assert_eq!(ping.hostname, "localhost");
+        assert_eq!(ping.port, 25570);
+    }
 
-        Ok(())
+    #[test]
+    fn read_ping_host_unknown_channel() {
+        let data: &[u8] = &[0x00, 0x01, 0x00, 0x41, 0x00, 0x00];
+        assert!(matches!(
+            PingHost::read_stream(&mut &data[..]),
+            Err(TypeError::InvalidLegacyPing(_))
+        ));
     }
 }
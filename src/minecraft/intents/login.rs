@@ -1,38 +1,59 @@
-use std::net::TcpStream;
-
-use simdnbt::owned::{NbtList, NbtTag};
+use std::{net::TcpStream, time::Duration};
 
 use crate::{
-    Player, ServerError, StatusConfig,
+    Player, ServerError,
     channel_message::{ChannelMessage, MessageData},
-    message::MessageGenerator,
+    config::ReissuePolicy,
+    message::{MessageContext, MessageGenerator},
     minecraft::{
         auth::authenticate,
-        encrypt::Aes128CfbEnc,
+        client_information::ClientInformation,
+        encrypt::{Aes128CfbDec, Aes128CfbEnc},
         handshake::Handshake,
         login_start::LoginStart,
-        packet::{Packet, ReadPacketData},
+        packet::{Packet, ReadPacketData, VersionedPacket},
         packets, protocol_version,
         server::ConnectionState,
     },
+    status::{StatusProvider, StatusRequest},
     token::TokenGenerator,
 };
 
-pub fn advance<T: TokenGenerator, M: MessageGenerator>(
+pub fn advance<S: StatusProvider>(
     stream: &mut TcpStream,
-    state: ConnectionState<T, M>,
+    state: ConnectionState,
     handshake: Handshake,
-    status_config: StatusConfig,
+    status: &S,
 ) -> Result<(), ServerError> {
     // if less than min supported protocol, kick as early as possible with legacy decription
     if handshake.protocol_version.0 < protocol_version::MIN_SUPPORTED_PROTOCOL {
-        packets::disconnect_login(&status_config.legacy_decription.unwrap_or_default())
+        let reason = status
+            .status(&StatusRequest::from(&handshake))
+            .legacy_description();
+        packets::DisconnectLogin::new(&reason, handshake.protocol_version.0)
+            .into_packet(handshake.protocol_version.0)?
             .write_stream(stream)?;
         return Ok(());
     }
 
-    let mut packet = Packet::from_stream(stream, 0x00)?;
-    let login_start = LoginStart::read(&mut packet.data)?;
+    // with nonce binding, only players joining through a registered nonce hostname get a token
+    let nonce = match &state.nonces {
+        Some(nonces) => match nonces.extract(&handshake.hostname()) {
+            Some(nonce) => Some(nonce),
+            None => {
+                packets::DisconnectLogin::new(
+                    &nonces.binding().unbound_message,
+                    handshake.protocol_version.0,
+                )
+                .into_packet(handshake.protocol_version.0)?
+                .write_stream(stream)?;
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
+    let login_start = LoginStart::from_stream(stream, handshake.protocol_version.0)?;
 
     let mut auth_res = authenticate(
         stream,
@@ -45,41 +66,133 @@ pub fn advance<T: TokenGenerator, M: MessageGenerator>(
     let player = Player {
         username: auth_res.profile.name,
         uuid: auth_res.profile.id,
+        vhost: state.vhost.clone(),
+        xuid: None,
     };
-    let token = send_disconnect(stream, state.clone(), &player, &mut auth_res.enc)?;
+
+    let (token, reused) = issue_token(&state, &player, nonce.as_deref());
+
+    let locale = match state.wait_for_locale {
+        true => read_locale(stream, &mut auth_res.dec),
+        false => None,
+    };
+
+    send_disconnect(
+        stream,
+        &state,
+        &token,
+        (handshake.protocol_version.0, locale.as_deref()),
+        &mut auth_res.enc,
+    )?;
+
+    complete_join(&state, player, token, reused, nonce);
+
+    Ok(())
+}
+
+/// Picks the token for a player, `true` if a still valid token is reused instead of generating one
+pub fn issue_token(
+    state: &ConnectionState,
+    player: &Player,
+    nonce: Option<&str>,
+) -> (String, bool) {
+    let reused = match state.reissue {
+        ReissuePolicy::ReuseExisting => {
+            state
+                .storage
+                .reusable_token(state.vhost.as_deref(), &player.uuid, nonce)
+        }
+        _ => None,
+    };
+
+    match reused {
+        Some(token) => (token, true),
+        None => (state.token.generate(player), false),
+    }
+}
+
+/// Stores the token once the player has been shown it, and announces the join
+pub fn complete_join(
+    state: &ConnectionState,
+    player: Player,
+    token: String,
+    reused: bool,
+    nonce: Option<String>,
+) {
+    // a reused token is already stored with its original expiry
+    if !reused {
+        let key = (state.vhost.clone(), state.token.normalize(&token));
+        let ttl = state.token.ttl(&player, &token);
+
+        match state.reissue {
+            ReissuePolicy::ReplacePrevious => {
+                state
+                    .storage
+                    .replace(key, token.clone(), player.clone(), nonce, ttl)
+            }
+            _ => state
+                .storage
+                .insert(key, token.clone(), player.clone(), nonce, ttl),
+        };
+    }
 
     state
         .broadcast
         .send(ChannelMessage::new(MessageData::OnJoin {
-            player,
-            token: token.to_string(),
+            player: Box::new(player),
+            token,
         }));
+}
 
-    Ok(())
+const LOCALE_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_CONFIGURATION_PACKETS: usize = 8;
+
+/// Waits for the client information in the configuration phase, which holds the clients locale
+///
+/// The client sends it right after acknowledging the login, together with its brand.  
+/// Any failure just means we don't know the locale, the player still gets their message.  
+fn read_locale(stream: &mut TcpStream, dec: &mut Aes128CfbDec) -> Option<String> {
+    let _ = stream.set_read_timeout(Some(LOCALE_TIMEOUT));
+
+    let mut locale = None;
+    for _ in 0..MAX_CONFIGURATION_PACKETS {
+        match Packet::read_compressed_encrypted_stream(stream, dec) {
+            Ok(mut packet) if packet.id.0 == ClientInformation::ID => {
+                locale = ClientInformation::read(&mut packet.data)
+                    .ok()
+                    .map(|info| info.locale.0);
+                break;
+            }
+            Ok(_) => continue,
+            Err(_) => break,
+        }
+    }
+
+    let _ = stream.set_read_timeout(None);
+    locale
 }
 
-fn send_disconnect<T: TokenGenerator, M: MessageGenerator>(
+fn send_disconnect(
     stream: &mut TcpStream,
-    state: ConnectionState<T, M>,
-    player: &Player,
+    state: &ConnectionState,
+    token: &str,
+    (protocol_version, locale): (i32, Option<&str>),
     enc: &mut Aes128CfbEnc,
-) -> Result<String, ServerError> {
-    let gen_token = state.token.generate(&player);
-    let msg = state
-        .message
-        .create_message(&state.token.display(&gen_token));
-
-    // text component must be either a compound, list of compounds, or a plain string
-    // we must verify because the message can be customized from the consumer
-    match msg {
-        NbtTag::Compound(_) => (),
-        NbtTag::String(_) => (),
-        NbtTag::List(NbtList::Compound(_)) => (),
-        NbtTag::List(NbtList::String(_)) => (),
-        _ => return Err(ServerError::InvalidMessageNbtTag(msg)),
-    }
+) -> Result<(), ServerError> {
+    let display = state.token.display(token);
+    let msg = state.message.clone().create_message(&MessageContext {
+        token: &display,
+        raw_token: token,
+        protocol_version,
+        locale,
+        bedrock: false,
+    });
 
-    packets::disconnect_configuration(msg).write_compressed_encrypted_stream(stream, enc)?;
+    packets::DisconnectConfiguration {
+        reason: msg.to_nbt(protocol_version),
+    }
+    .into_packet(protocol_version)?
+    .write_compressed_encrypted_stream(stream, enc)?;
 
-    Ok(gen_token)
+    Ok(())
 }
@@ -1,112 +1,317 @@
-use std::{io::Cursor, net::TcpStream};
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::Cursor,
+    net::TcpStream,
+    sync::{Arc, Mutex, MutexGuard},
+};
 
-use bytes::Buf;
 use constcat::concat;
-use image::{ImageFormat, RgbaImage};
-use serde::Serialize;
-use serde_json::Value;
+use image::{DynamicImage, ImageFormat, RgbaImage, imageops::FilterType};
 
 use crate::{
     ServerError,
-    config::{MIN_SUPPORTED_VERSION, StatusConfig},
-    message::MessageGenerator,
+    config::{MIN_SUPPORTED_VERSION, StatusConfig, resize_favicon},
     minecraft::{
-        handshake::Handshake, packet::Packet, packets, protocol_version, server::ConnectionState,
+        handshake::Handshake,
+        packet::{Packet, ReadPacketData, State, VersionedPacket},
+        packets, protocol_version,
+        server::ConnectionState,
+        string::PacketString,
+    },
+    status::{
+        SamplePlayer, StatusProvider, StatusRequest, StatusResponse, StatusResponsePlayers,
+        StatusVersion,
     },
-    token::TokenGenerator,
 };
 
-pub fn advance<T: TokenGenerator, M: MessageGenerator>(
+pub fn advance<S: StatusProvider>(
     stream: &mut TcpStream,
-    _state: ConnectionState<T, M>,
+    state: ConnectionState,
     handshake: Handshake,
-    config: StatusConfig,
+    status: &S,
 ) -> Result<(), ServerError> {
-    let _ = Packet::from_stream(stream, 0x00)?;
+    let _ = Packet::from_stream(stream, State::Status, 0x00)?;
 
-    let status = get_status(handshake.protocol_version.0, config)?;
-    packets::status_response(status).write_stream(stream)?;
+    let request = StatusRequest::from(&handshake);
+    let status = state
+        .status_cache
+        .get_status(state.vhost.as_deref(), &request, status)?;
+    let protocol_version = handshake.protocol_version.0;
+    packets::StatusResponse {
+        json: PacketString(status),
+    }
+    .into_packet(protocol_version)?
+    .write_stream(stream)?;
 
-    let mut ping = Packet::from_stream(stream, 0x01)?;
-    let timestamp = ping.data.get_i64();
+    let mut ping = Packet::from_stream(stream, State::Status, 0x01)?;
+    let timestamp = i64::read(&mut ping.data)?;
 
-    packets::pong_response(timestamp).write_stream(stream)?;
+    packets::PongResponse { timestamp }
+        .into_packet(protocol_version)?
+        .write_stream(stream)?;
 
     Ok(())
 }
 
-fn get_status(protocol: i32, config: StatusConfig) -> Result<String, ServerError> {
-    let status = StatusResponse {
-        version: Version {
-            name: concat!(MIN_SUPPORTED_VERSION, "+").to_string(),
-            protocol,
-        },
-        players: Some(Players {
-            max: 0,
-            online: 0,
-            sample: Vec::default(),
-        }),
-        description: if protocol < protocol_version::MIN_SUPPORTED_PROTOCOL {
-            Some(Value::String(config.legacy_decription.unwrap_or_default()))
-        } else {
-            config.description
-        },
-        favicon: encode_favicon(config.favicon)?,
-        enforces_secure_chat: false,
-    };
-
-    Ok(serde_json::to_string(&status)?)
+/// Clients that serialize the status the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ProtocolBucket {
+    /// Too old to join, shown the legacy description
+    Unsupported,
+    /// Uses `clickEvent` and `hoverEvent`
+    CamelCase,
+    /// 1.21.5+, uses `click_event` and `hover_event`
+    SnakeCase,
 }
 
-fn encode_favicon(favicon: Option<RgbaImage>) -> Result<Option<String>, ServerError> {
-    use base64::prelude::*;
+impl ProtocolBucket {
+    fn of(protocol: i32) -> Self {
+        match protocol {
+            p if p < protocol_version::MIN_SUPPORTED_PROTOCOL => Self::Unsupported,
+            p if p < protocol_version::V1_21_5 => Self::CamelCase,
+            _ => Self::SnakeCase,
+        }
+    }
+}
 
-    let favicon = match favicon {
-        Some(f) => f,
-        None => return Ok(None),
-    };
+/// Responses of cacheable providers by virtual host and protocol bucket
+type CachedResponses = HashMap<(Option<String>, ProtocolBucket), Arc<CachedStatus>>;
 
-    if favicon.width() > 64 || favicon.height() > 64 {
-        return Ok(None);
+/// A status response with everything but the version already serialized
+///
+/// The version is the only part that differs within a bucket, so it's spliced in per request.  
+#[derive(Debug)]
+struct CachedStatus {
+    version: StatusVersion,
+    /// The other fields as a JSON object
+    rest: String,
+}
+
+impl CachedStatus {
+    fn new(status: StatusResponse) -> Result<Self, ServerError> {
+        let mut rest = serde_json::to_value(&status)?;
+        if let Some(fields) = rest.as_object_mut() {
+            fields.remove("version");
+        }
+
+        Ok(Self {
+            version: status.version,
+            rest: rest.to_string(),
+        })
     }
 
-    let mut img = Cursor::new(Vec::new());
-    favicon.write_to(&mut img, ImageFormat::Png)?;
+    fn to_json(&self, protocol: i32) -> Result<String, ServerError> {
+        let version = StatusVersion {
+            // anything but their own protocol marks older clients as outdated
+            protocol: match self.version.configured {
+                Some(configured) => configured,
+                None if protocol < protocol_version::MIN_SUPPORTED_PROTOCOL => {
+                    protocol_version::MIN_SUPPORTED_PROTOCOL
+                }
+                None => protocol,
+            },
+            ..self.version.clone()
+        };
+        let version = serde_json::to_string(&version)?;
 
-    Ok(Some(format!(
-        "data:image/png;base64,{}",
-        BASE64_STANDARD.encode(img.get_ref())
-    )))
+        // rest without its opening brace
+        Ok(match &self.rest[1..] {
+            "}" => format!(r#"{{"version":{version}}}"#),
+            rest => format!(r#"{{"version":{version},{rest}"#),
+        })
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct StatusResponse {
-    version: Version,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    players: Option<Players>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    description: Option<Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    favicon: Option<String>,
-    #[serde(rename = "enforcesSecureChat")]
-    enforces_secure_chat: bool,
+/// Caches what is expensive about status responses, shared by all connections
+///
+/// Favicons are cached by their pixels for any provider, whole responses only  
+/// if the provider is [`cacheable`](StatusProvider::cacheable), since the config can't change then.  
+#[derive(Debug, Clone, Default)]
+pub struct StatusCache {
+    responses: Arc<Mutex<CachedResponses>>,
+    /// Favicon data urls by a hash of the image
+    favicons: Arc<Mutex<HashMap<u64, String>>>,
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct Version {
-    name: String,
-    protocol: i32,
+impl StatusCache {
+    /// Changing providers could generate a new favicon every time, so the cache can't grow forever
+    const MAX_FAVICONS: usize = 64;
+
+    /// Returns the serialized status for a request, from the cache if possible
+    pub fn get_status<S: StatusProvider>(
+        &self,
+        vhost: Option<&str>,
+        request: &StatusRequest,
+        provider: &S,
+    ) -> Result<String, ServerError> {
+        let protocol = request.protocol_version.unwrap_or_default();
+        let key = (vhost.map(str::to_string), ProtocolBucket::of(protocol));
+
+        let cached = match provider.cacheable() {
+            true => self.lock_responses().get(&key).cloned(),
+            false => None,
+        };
+        let status = match cached {
+            Some(status) => status,
+            None => {
+                let status = self.build_status(protocol, provider.status(request))?;
+                let status = Arc::new(CachedStatus::new(status)?);
+                if provider.cacheable() {
+                    self.lock_responses().insert(key, status.clone());
+                }
+                status
+            }
+        };
+
+        status.to_json(protocol)
+    }
+
+    fn lock_responses(&self) -> MutexGuard<'_, CachedResponses> {
+        self.responses.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn build_status(
+        &self,
+        protocol: i32,
+        config: StatusConfig,
+    ) -> Result<StatusResponse, ServerError> {
+        Ok(StatusResponse {
+            version: StatusVersion {
+                name: config
+                    .version_name
+                    .clone()
+                    .unwrap_or_else(|| concat!(MIN_SUPPORTED_VERSION, "+").to_string()),
+                protocol,
+                configured: config.protocol,
+            },
+            players: config
+                .players
+                .as_ref()
+                .map(|players| StatusResponsePlayers {
+                    max: players.max,
+                    online: players.online,
+                    sample: players
+                        .sample
+                        .iter()
+                        .map(|p| SamplePlayer {
+                            name: p.username.clone(),
+                            id: p.uuid.to_string(),
+                        })
+                        .collect(),
+                }),
+            description: if protocol < protocol_version::MIN_SUPPORTED_PROTOCOL {
+                Some(config.legacy_description().to_json(protocol))
+            } else {
+                config.description.map(|d| d.to_json(protocol))
+            },
+            favicon: match &config.favicon {
+                Some(favicon) => Some(self.favicon(favicon)?),
+                None => None,
+            },
+            enforces_secure_chat: false,
+        })
+    }
+
+    fn favicon(&self, favicon: &RgbaImage) -> Result<String, ServerError> {
+        let mut hasher = DefaultHasher::new();
+        favicon.dimensions().hash(&mut hasher);
+        favicon.as_raw().hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if let Some(url) = self
+            .favicons
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&hash)
+        {
+            return Ok(url.clone());
+        }
+
+        let url = encode_favicon(favicon)?;
+
+        let mut favicons = self.favicons.lock().unwrap_or_else(|e| e.into_inner());
+        if favicons.len() >= Self::MAX_FAVICONS {
+            favicons.clear();
+        }
+        favicons.insert(hash, url.clone());
+
+        Ok(url)
+    }
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct Players {
-    max: i32,
-    online: i32,
-    sample: Vec<Player>,
+fn encode_favicon(favicon: &RgbaImage) -> Result<String, ServerError> {
+    use base64::prelude::*;
+
+    let size = StatusConfig::FAVICON_SIZE;
+    let mut img = Cursor::new(Vec::new());
+    // clients only show 64x64 favicons, anything else set directly is resized here
+    if favicon.dimensions() != (size, size) {
+        let resized = resize_favicon(
+            DynamicImage::ImageRgba8(favicon.clone()),
+            FilterType::Triangle,
+        );
+        resized.write_to(&mut img, ImageFormat::Png)?;
+    } else {
+        favicon.write_to(&mut img, ImageFormat::Png)?;
+    }
+
+    Ok(format!(
+        "data:image/png;base64,{}",
+        BASE64_STANDARD.encode(img.get_ref())
+    ))
 }
 
-#[derive(Debug, Clone, Serialize)]
-struct Player {
-    name: String,
-    id: String,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextComponent;
+
+    fn request(protocol: i32) -> StatusRequest {
+        StatusRequest {
+            protocol_version: Some(protocol),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cached_status_protocol() {
+        let cache = StatusCache::default();
+        let config = StatusConfig {
+            description: Some(TextComponent::text("Hello")),
+            ..Default::default()
+        };
+
+        let protocol = protocol_version::V1_21_5;
+        for protocol in [protocol, protocol + 1, protocol] {
+            let json = cache.get_status(None, &request(protocol), &config).unwrap();
+            let status: StatusResponse = serde_json::from_str(&json).unwrap();
+            assert_eq!(status.version.protocol, protocol);
+            assert!(status.description.is_some());
+        }
+        assert_eq!(cache.lock_responses().len(), 1);
+
+        let old = protocol_version::MIN_SUPPORTED_PROTOCOL - 1;
+        let json = cache.get_status(None, &request(old), &config).unwrap();
+        let status: StatusResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            status.version.protocol,
+            protocol_version::MIN_SUPPORTED_PROTOCOL
+        );
+    }
+
+    #[test]
+    fn cached_status_configured_protocol() {
+        let cache = StatusCache::default();
+        let config = StatusConfig {
+            protocol: Some(5),
+            ..Default::default()
+        };
+
+        let json = cache
+            .get_status(None, &request(protocol_version::V1_21_5), &config)
+            .unwrap();
+        let status: StatusResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(status.version.protocol, 5);
+    }
 }
@@ -1,18 +1,17 @@
 use std::net::TcpStream;
 
 use crate::{
-    ServerError, StatusConfig,
-    message::MessageGenerator,
+    ServerError,
     minecraft::{handshake::Handshake, intents::login, server::ConnectionState},
-    token::TokenGenerator,
+    status::StatusProvider,
 };
 
-pub fn advance<T: TokenGenerator, M: MessageGenerator>(
+pub fn advance<S: StatusProvider>(
     stream: &mut TcpStream,
-    state: ConnectionState<T, M>,
+    state: ConnectionState,
     handshake: Handshake,
-    status_config: StatusConfig,
+    status: &S,
 ) -> Result<(), ServerError> {
     // just handle it as a login packet
-    login::advance(stream, state, handshake, status_config)
+    login::advance(stream, state, handshake, status)
 }
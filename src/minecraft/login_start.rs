@@ -1,26 +1,82 @@
-use bytes::Buf;
+use mc_headless_auth_derive::Packet;
 use uuid::Uuid;
 
-use crate::{
-    error::TypeError,
-    minecraft::{packet::ReadPacketData, string::PacketString},
-};
+use crate::minecraft::{protocol_version, string::PacketString};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Packet)]
+#[packet(id = 0x00, state = Login)]
 pub struct LoginStart {
-    pub name: PacketString,
+    pub name: PacketString<16>,
+    /// Before 1.20.2 it was optional or not sent at all, which we don't need to read
+    #[packet(since = protocol_version::V1_20_2)]
     pub uuid: Option<Uuid>,
 }
 
-impl ReadPacketData for LoginStart {
-    fn read(data: &mut bytes::Bytes) -> Result<Self, TypeError> {
-        let name = PacketString::read(data)?;
-        let uuid = if data.len() > 0 {
-            Some(Uuid::from_u128(data.get_u128()))
-        } else {
-            None
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use uuid::uuid;
+
+    use crate::{
+        error::TypeError,
+        minecraft::{
+            packet::{ReadPacketData, VersionedPacket},
+            protocol_version,
+            string::PacketString,
+        },
+    };
+
+    use super::LoginStart;
+
+    #[test]
+    fn with_uuid() {
+        let mut data = Bytes::from_static(
+            b"\x05Notch\x06\x9A\x79\xF4\x44\xE9\x47\x26\xA5\xBE\xFC\xA9\x0E\x38\xAA\xF5",
+        );
+        let login = LoginStart::read(&mut data).unwrap();
+
+        assert_eq!(login.name.0, "Notch");
+        assert_eq!(
+            login.uuid,
+            Some(uuid!("069a79f4-44e9-4726-a5be-fca90e38aaf5"))
+        );
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn without_uuid() {
+        // clients before 1.19 only send the name
+        let login = LoginStart::read_versioned(&mut Bytes::from_static(b"\x05Notch"), 758).unwrap();
+
+        assert_eq!(login.name.0, "Notch");
+        assert_eq!(login.uuid, None);
+    }
+
+    #[test]
+    fn write_without_uuid() {
+        let login = LoginStart {
+            name: PacketString("Notch".to_string()),
+            uuid: None,
+        };
+
+        let mut data = BytesMut::new();
+        login.write_versioned(&mut data, 758).unwrap();
+        assert_eq!(&data[..], b"\x05Notch");
+    }
+
+    #[test]
+    fn write_missing_uuid() {
+        let login = LoginStart {
+            name: PacketString("Notch".to_string()),
+            uuid: None,
         };
 
-        Ok(Self { name, uuid })
+        let err = login
+            .write_versioned(&mut BytesMut::new(), protocol_version::V1_20_2)
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            TypeError::MissingField("LoginStart::uuid", protocol_version::V1_20_2)
+        ));
     }
 }
@@ -1,122 +1,229 @@
 pub mod array;
 pub mod auth;
+pub mod bedrock;
+pub mod bit_set;
+pub mod client_information;
 pub mod encrypt;
 pub mod handshake;
 pub mod hash;
+pub mod identifier;
 pub mod intents;
 pub mod login_start;
+pub mod nbt;
 pub mod optional;
 pub mod packet;
+pub mod position;
+pub mod query;
 pub mod server;
 pub mod string;
 pub mod uuid;
 pub mod var_int;
+pub mod var_long;
 
 /// Special Protocol Versions that are used in special cases
 pub mod protocol_version {
+    pub const V1_21_5: i32 = 770;
     pub const V1_21_2: i32 = 768;
     pub const V1_20_5: i32 = 766;
-    pub const V1_16: i32 = 735;
-    pub const V1_7_6: i32 = 5;
+    pub const V1_20_2: i32 = 764;
 
     pub const MIN_SUPPORTED_PROTOCOL: i32 = V1_21_2;
 }
 
 mod packets {
-    use bytes::{BufMut, BytesMut};
-    use serde_json::json;
+    use mc_headless_auth_derive::Packet;
     use simdnbt::owned::NbtTag;
-
-    use crate::minecraft::{
-        array::Array,
-        auth::GameProfileProps,
-        packet::{Packet, WritePacketData},
-        string::PacketString,
-        var_int::VarInt,
+    use uuid::Uuid;
+
+    use crate::{
+        minecraft::{
+            array::Array, auth::GameProfileProps, protocol_version, string::PacketString,
+            var_int::VarInt,
+        },
+        text::TextComponent,
     };
 
-    pub fn encryption_request(
-        server_id: &str,
-        public_key: Vec<u8>,
-        verify_token: Vec<u8>,
-        should_authenticate: bool,
-    ) -> Packet {
-        let mut data = BytesMut::new();
-
-        PacketString::new(server_id).write(&mut data);
-        Array::new(public_key).write(&mut data);
-        Array::new(verify_token).write(&mut data);
-        data.put_u8(should_authenticate as u8);
-
-        Packet::new(0x01, data.into())
+    #[derive(Debug, Clone, Packet)]
+    #[packet(id = 0x01, state = Login)]
+    pub struct EncryptionRequest {
+        pub server_id: PacketString<20>,
+        pub public_key: Array<u8>,
+        pub verify_token: Array<u8>,
+        #[packet(since = protocol_version::V1_20_5)]
+        pub should_authenticate: Option<bool>,
     }
 
-    pub fn set_compression(threshold: i32) -> Packet {
-        let mut data = BytesMut::new();
-
-        VarInt(threshold).write(&mut data);
-
-        Packet::new(0x03, data.into())
+    #[derive(Debug, Clone, Packet)]
+    #[packet(id = 0x03, state = Login)]
+    pub struct SetCompression {
+        pub threshold: VarInt,
     }
 
-    pub fn login_success(
-        uuid: Vec<u8>,
-        username: &str,
-        _properties: Vec<GameProfileProps>,
-    ) -> Packet {
-        let mut data = BytesMut::new();
-
-        data.extend_from_slice(&uuid);
-        PacketString::new(username).write(&mut data);
-
-        VarInt(_properties.len() as i32).write(&mut data);
-        for prop in _properties {
-            PacketString::new(prop.name).write(&mut data);
-            PacketString::new(prop.value).write(&mut data);
-            prop.signature
-                .map(|s| PacketString::new(s))
-                .write(&mut data);
-        }
-
-        Packet::new(0x02, data.into())
+    #[derive(Debug, Clone, Packet)]
+    #[packet(id = 0x02, state = Login)]
+    pub struct LoginSuccess {
+        pub uuid: Uuid,
+        pub username: PacketString<16>,
+        pub properties: Array<GameProfileProps>,
+        /// Only sent by 1.20.5 and 1.21
+        #[packet(since = protocol_version::V1_20_5, until = protocol_version::V1_21_2)]
+        pub strict_error_handling: Option<bool>,
     }
 
-    pub fn status_response(status: String) -> Packet {
-        let mut data = BytesMut::new();
-
-        PacketString::new(status).write(&mut data);
+    #[derive(Debug, Clone, Packet)]
+    #[packet(id = 0x00, state = Status)]
+    pub struct StatusResponse {
+        pub json: PacketString,
+    }
 
-        Packet::new(0x00, data.into())
+    #[derive(Debug, Clone, Packet)]
+    #[packet(id = 0x01, state = Status)]
+    pub struct PongResponse {
+        pub timestamp: i64,
     }
 
-    pub fn pong_response(timestamp: i64) -> Packet {
-        let mut data = BytesMut::new();
+    #[derive(Debug, Clone, Packet)]
+    #[packet(id = 0x02, state = Configuration)]
+    pub struct DisconnectConfiguration {
+        pub reason: NbtTag,
+    }
 
-        data.put_i64(timestamp);
+    #[derive(Debug, Clone, Packet)]
+    #[packet(id = 0x00, state = Login)]
+    pub struct DisconnectLogin {
+        pub reason: PacketString,
+    }
 
-        Packet::new(0x01, data.into())
+    impl DisconnectLogin {
+        pub fn new(reason: &TextComponent, protocol_version: i32) -> Self {
+            Self {
+                reason: PacketString::new(reason.to_json(protocol_version)),
+            }
+        }
     }
 
-    pub fn disconnect_configuration(text_component: NbtTag) -> Packet {
-        let mut data = BytesMut::new();
+    #[cfg(test)]
+    mod tests {
+        use bytes::BytesMut;
+        use uuid::uuid;
+
+        use crate::{
+            error::TypeError,
+            minecraft::{
+                array::Array,
+                auth::GameProfileProps,
+                packet::{Packet, VersionedPacket, WritePacketData},
+                protocol_version,
+                string::PacketString,
+                var_int::VarInt,
+            },
+            text::TextComponent,
+        };
+
+        use super::{
+            DisconnectConfiguration, EncryptionRequest, LoginSuccess, SetCompression,
+            StatusResponse,
+        };
+
+        /// The whole frame, with the length and id in front of the data
+        fn frame(packet: Result<Packet, TypeError>) -> Vec<u8> {
+            let mut data = BytesMut::new();
+            packet.unwrap().write(&mut data);
+            data.to_vec()
+        }
 
-        let mut msg = Vec::new();
-        text_component.write(&mut msg);
-        data.extend_from_slice(&msg);
+        fn encryption_request() -> EncryptionRequest {
+            EncryptionRequest {
+                server_id: PacketString(String::new()),
+                public_key: Array::new(vec![0x30, 0x81]),
+                verify_token: Array::new(vec![1, 2, 3, 4]),
+                should_authenticate: Some(true),
+            }
+        }
 
-        Packet::new(0x02, data.into())
-    }
+        #[test]
+        fn encryption_request_fixture() {
+            assert_eq!(
+                frame(encryption_request().into_packet(protocol_version::V1_21_5)),
+                b"\x0B\x01\x00\x02\x30\x81\x04\x01\x02\x03\x04\x01"
+            );
+            // should_authenticate was added in 1.20.5
+            assert_eq!(
+                frame(encryption_request().into_packet(protocol_version::V1_20_2)),
+                b"\x0A\x01\x00\x02\x30\x81\x04\x01\x02\x03\x04"
+            );
+        }
 
-    pub fn disconnect_login(reason: &str) -> Packet {
-        let mut data = BytesMut::new();
+        #[test]
+        fn login_success() {
+            let uuid = uuid!("069a79f4-44e9-4726-a5be-fca90e38aaf5");
+            let packet = LoginSuccess {
+                uuid,
+                username: PacketString("Notch".to_string()),
+                properties: Array::new(vec![GameProfileProps {
+                    name: "textures".to_string(),
+                    value: "e30=".to_string(),
+                    signature: Some("sig".to_string()),
+                }]),
+                strict_error_handling: Some(true),
+            };
+
+            let mut expected = b"\x2B\x02".to_vec();
+            expected.extend_from_slice(uuid.as_bytes());
+            expected.extend_from_slice(b"\x05Notch\x01\x08textures\x04e30=\x01\x03sig");
+            assert_eq!(
+                frame(packet.clone().into_packet(protocol_version::V1_21_5)),
+                expected
+            );
+
+            // 1.20.5 and 1.21 have strict error handling at the end
+            expected[0] += 1;
+            expected.push(0x01);
+            assert_eq!(
+                frame(packet.into_packet(protocol_version::V1_20_5)),
+                expected
+            );
+        }
 
-        let reason = json!({
-            "text": reason
-        });
-        let json = serde_json::to_string(&reason).unwrap();
+        #[test]
+        fn set_compression() {
+            let packet = |threshold| {
+                SetCompression {
+                    threshold: VarInt(threshold),
+                }
+                .into_packet(protocol_version::V1_21_5)
+            };
+
+            assert_eq!(frame(packet(256)), [0x03, 0x03, 0x80, 0x02]);
+            assert_eq!(
+                frame(packet(-1)),
+                [0x06, 0x03, 0xFF, 0xFF, 0xFF, 0xFF, 0x0F]
+            );
+        }
 
-        PacketString::new(json).write(&mut data);
+        #[test]
+        fn status_response() {
+            let packet = StatusResponse {
+                json: PacketString::new(r#"{"a":1}"#),
+            };
 
-        Packet::new(0x00, data.into())
+            assert_eq!(
+                frame(packet.into_packet(protocol_version::V1_21_5)),
+                b"\x09\x00\x07{\"a\":1}"
+            );
+        }
+
+        #[test]
+        fn disconnect_configuration() {
+            let packet = DisconnectConfiguration {
+                reason: TextComponent::text("Bye").to_nbt(protocol_version::V1_21_5),
+            };
+
+            // a nameless compound with one string tag, as NBT is sent since 1.20.2
+            assert_eq!(
+                frame(packet.into_packet(protocol_version::V1_21_5)),
+                b"\x0F\x02\x0A\x08\x00\x04text\x00\x03Bye\x00"
+            );
+        }
     }
 }
@@ -0,0 +1,55 @@
+use std::io::Cursor;
+
+use bytes::{Buf, Bytes, BytesMut};
+use simdnbt::owned::{NbtTag, read_tag};
+
+use crate::{
+    error::TypeError,
+    minecraft::packet::{ReadPacketData, WritePacketData},
+};
+
+/// Network NBT as sent since 1.20.2, the tag id and then its value without a name
+impl ReadPacketData for NbtTag {
+    fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+        let mut cursor = Cursor::new(&data[..]);
+        let tag = read_tag(&mut cursor).map_err(simdnbt::Error::from)?;
+        data.advance(cursor.position() as usize);
+
+        Ok(tag)
+    }
+}
+
+impl WritePacketData for NbtTag {
+    fn write(self, data: &mut BytesMut) {
+        let mut nbt = Vec::new();
+        NbtTag::write(&self, &mut nbt);
+        data.extend_from_slice(&nbt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use simdnbt::owned::NbtTag;
+
+    use crate::{error::TypeError, minecraft::packet::ReadPacketData, text::TextComponent};
+
+    #[test]
+    fn read_text_component() {
+        let mut data = Bytes::from_static(b"\x0A\x08\x00\x04text\x00\x03Bye\x00\xFF");
+        let tag = NbtTag::read(&mut data).unwrap();
+
+        assert_eq!(tag, TextComponent::text("Bye").to_nbt(0));
+        // only the tag is consumed
+        assert_eq!(&data[..], [0xFF]);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut data = Bytes::from_static(b"\x0A\x08\x00\x04te");
+        assert!(matches!(
+            NbtTag::read(&mut data),
+            Err(TypeError::NbtError(simdnbt::Error::UnexpectedEof))
+        ));
+    }
+}
@@ -1,6 +1,20 @@
 use bytes::BufMut;
 
-use crate::minecraft::packet::WritePacketData;
+use crate::{
+    error::TypeError,
+    minecraft::packet::{ReadPacketData, WritePacketData},
+};
+
+/// A prefixed optional, a bool telling if the value follows
+impl<T: ReadPacketData> ReadPacketData for Option<T> {
+    fn read(data: &mut bytes::Bytes) -> Result<Self, TypeError> {
+        if bool::read(data)? {
+            Ok(Some(T::read(data)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
 
 impl<T: WritePacketData> WritePacketData for Option<T> {
     fn write(self, data: &mut bytes::BytesMut) {
@@ -3,14 +3,14 @@ use std::{
     net::TcpStream,
 };
 
-use bytes::{BufMut, Bytes, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use miniz_oxide::{deflate, inflate};
 
 use crate::{
     error::TypeError,
     minecraft::{
         encrypt::{Aes128CfbDec, Aes128CfbEnc, decrypt_packet, encrypt_packet},
-        intents::legacy_ping::compare_init_bytes,
+        intents::legacy_ping::{_1dot6::PingHost, compare_init_bytes},
         var_int::VarInt,
     },
 };
@@ -44,18 +44,18 @@ impl Packet {
         // check the first 3 bytes against some patterns
         // and if any of these match, its a legacy that we handle differently
         // since their format is WAY different than the modern packet format
-        if let Some(legacy) = compare_init_bytes(packet_ident) {
+        if let Some(legacy) = compare_init_bytes(packet_ident, stream)? {
             return Ok(legacy);
         }
 
         let mut packet_ident = packet_ident.to_vec();
         let len = VarInt::read_via_stream(stream, &mut packet_ident)?;
-        let buf_len = len.0 as usize - packet_ident.len();
-
-        if len.0 > Self::PACKET_LIMIT as i32 {
+        if len.0 < 0 || len.0 > Self::PACKET_LIMIT as i32 {
             return Err(TypeError::PacketSizeExceedsLimit(len.0));
         }
 
+        let buf_len = (len.0 as usize).saturating_sub(packet_ident.len());
+
         let mut data = vec![0u8; buf_len];
 
         stream
@@ -76,21 +76,25 @@ impl Packet {
         }))
     }
 
-    pub fn from_stream(stream: &mut TcpStream, expected_id: i32) -> Result<Self, TypeError> {
+    pub fn from_stream(
+        stream: &mut TcpStream,
+        state: State,
+        expected_id: i32,
+    ) -> Result<Self, TypeError> {
         let len = VarInt::read_via_stream(stream, &mut Vec::new())?;
 
-        if len.0 > Self::PACKET_LIMIT as i32 {
+        if len.0 < 0 || len.0 > Self::PACKET_LIMIT as i32 {
             return Err(TypeError::PacketSizeExceedsLimit(len.0));
         }
 
         let mut data = vec![0u8; len.0 as usize];
-        stream.read_exact(&mut data).unwrap();
+        stream.read_exact(&mut data).map_err(TypeError::ReadError)?;
 
         let mut data = Bytes::from_owner(data);
         let id = VarInt::read(&mut data)?;
 
         if id.0 != expected_id {
-            return Err(TypeError::UnexpectedPacketId(expected_id, id.0));
+            return Err(TypeError::UnexpectedPacketId(state, expected_id, id.0));
         }
 
         Ok(Self {
@@ -163,25 +167,52 @@ impl Packet {
     pub fn from_compressed_encrypted_stream(
         stream: &mut TcpStream,
         dec: &mut Aes128CfbDec,
+        state: State,
         expected_id: i32,
+    ) -> Result<Self, TypeError> {
+        let packet = Self::read_compressed_encrypted_stream(stream, dec)?;
+
+        if packet.id.0 != expected_id {
+            return Err(TypeError::UnexpectedPacketId(
+                state,
+                expected_id,
+                packet.id.0,
+            ));
+        }
+
+        Ok(packet)
+    }
+
+    /// Reads the next compressed + encrypted packet, whatever its id is
+    pub fn read_compressed_encrypted_stream(
+        stream: &mut TcpStream,
+        dec: &mut Aes128CfbDec,
     ) -> Result<Self, TypeError> {
         let len = VarInt::read_via_encrypted_stream(stream, dec)?;
+        if len.0 < 0 || len.0 > Self::PACKET_LIMIT as i32 {
+            return Err(TypeError::PacketSizeExceedsLimit(len.0));
+        }
+
         let mut data = vec![0u8; len.0 as usize];
-        stream.read_exact(&mut data).unwrap();
+        stream
+            .read_exact(&mut data)
+            .map_err(|e| TypeError::ReadError(e))?;
         decrypt_packet(dec, &mut data)?;
         let mut data = Bytes::from_owner(data);
 
         let data_len = VarInt::read(&mut data)?;
-        // id + data is compressed
-        let mut data = Bytes::from_owner(
-            inflate::decompress_to_vec_zlib(&data).map_err(|e| TypeError::DecompressError(e))?,
-        );
+        // a data length of 0 means the packet was below the threshold and isn't compressed
+        // otherwise id + data is compressed
+        let mut data = if data_len.0 == 0 {
+            data
+        } else {
+            Bytes::from_owner(
+                inflate::decompress_to_vec_zlib_with_limit(&data, Self::PACKET_LIMIT)
+                    .map_err(|e| TypeError::DecompressError(e))?,
+            )
+        };
         let id = VarInt::read(&mut data)?;
 
-        if id.0 != expected_id {
-            return Err(TypeError::UnexpectedPacketId(expected_id, id.0));
-        }
-
         Ok(Self {
             length: data_len,
             id,
@@ -204,6 +235,39 @@ impl WritePacketData for Packet {
     }
 }
 
+/// What a connection is doing, packet ids only mean something together with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum State {
+    Handshake,
+    Status,
+    Login,
+    Configuration,
+}
+
+/// A packet whose fields depend on the protocol version, derived with `#[derive(Packet)]`
+///
+/// https://minecraft.wiki/w/Java_Edition_protocol/Packets
+pub trait VersionedPacket: Sized {
+    const ID: i32;
+    const STATE: State;
+
+    fn read_versioned(data: &mut Bytes, protocol_version: i32) -> Result<Self, TypeError>;
+    fn write_versioned(self, data: &mut BytesMut, protocol_version: i32) -> Result<(), TypeError>;
+
+    /// Reads the next packet off the stream, which has to be this one
+    fn from_stream(stream: &mut TcpStream, protocol_version: i32) -> Result<Self, TypeError> {
+        let mut packet = Packet::from_stream(stream, Self::STATE, Self::ID)?;
+        Self::read_versioned(&mut packet.data, protocol_version)
+    }
+
+    fn into_packet(self, protocol_version: i32) -> Result<Packet, TypeError> {
+        let mut data = BytesMut::new();
+        self.write_versioned(&mut data, protocol_version)?;
+
+        Ok(Packet::new(Self::ID, data.into()))
+    }
+}
+
 pub trait ReadPacketData {
     fn read(data: &mut Bytes) -> Result<Self, TypeError>
     where
@@ -223,15 +287,59 @@ pub trait WritePacketData {
     }
 }
 
-impl WritePacketData for u8 {
+/// Errors instead of letting `bytes` panic when a packet ends early
+pub(crate) fn check_remaining(data: &Bytes, len: usize) -> Result<(), TypeError> {
+    if data.remaining() < len {
+        return Err(TypeError::UnexpectedEnd(len, data.remaining()));
+    }
+
+    Ok(())
+}
+
+macro_rules! primitive {
+    ($($ty:ty => $get:ident, $put:ident;)*) => {$(
+        impl ReadPacketData for $ty {
+            fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+                check_remaining(data, size_of::<$ty>())?;
+                Ok(data.$get())
+            }
+        }
+
+        impl WritePacketData for $ty {
+            fn write(self, data: &mut BytesMut) {
+                data.$put(self);
+            }
+        }
+    )*};
+}
+
+// all big endian, like Java writes them
+primitive! {
+    u8 => get_u8, put_u8;
+    i8 => get_i8, put_i8;
+    u16 => get_u16, put_u16;
+    i16 => get_i16, put_i16;
+    i32 => get_i32, put_i32;
+    i64 => get_i64, put_i64;
+    f32 => get_f32, put_f32;
+    f64 => get_f64, put_f64;
+}
+
+impl ReadPacketData for bool {
+    fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+        Ok(u8::read(data)? != 0)
+    }
+}
+
+impl WritePacketData for bool {
     fn write(self, data: &mut BytesMut) {
-        data.put_u8(self);
+        data.put_u8(self as u8);
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum InitPacket {
-    V1_6,
+    V1_6(PingHost),
     V1_4To1_5,
     Vbeta1_8To1_3,
     V1_7Above(Packet),
@@ -0,0 +1,86 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::{
+    error::TypeError,
+    minecraft::packet::{ReadPacketData, WritePacketData},
+};
+
+/// A block position packed into a long, 26 bits for x and z and 12 bits for y
+///
+/// https://minecraft.wiki/w/Java_Edition_protocol/Data_types#Position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(not(test), allow(dead_code))] // no packet we handle has one yet
+pub(crate) struct Position {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl Position {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl ReadPacketData for Position {
+    fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+        let value = i64::read(data)?;
+
+        // shifting left first and then back sign extends each field
+        Ok(Self {
+            x: (value >> 38) as i32,
+            y: (value << 52 >> 52) as i32,
+            z: (value << 26 >> 38) as i32,
+        })
+    }
+}
+
+impl WritePacketData for Position {
+    fn write(self, data: &mut BytesMut) {
+        let value = ((self.x as i64 & 0x3FFFFFF) << 38)
+            | ((self.z as i64 & 0x3FFFFFF) << 12)
+            | (self.y as i64 & 0xFFF);
+        value.write(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use proptest::prelude::*;
+
+    use crate::minecraft::packet::{ReadPacketData, WritePacketData};
+
+    use super::Position;
+
+    #[test]
+    fn fixtures() {
+        for (position, bytes) in [
+            (Position::new(0, 0, 0), [0x00; 8]),
+            (
+                Position::new(18357644, 831, -20882616),
+                [0x46, 0x07, 0x63, 0x2C, 0x15, 0xB4, 0x83, 0x3F],
+            ),
+            (Position::new(-1, -1, -1), [0xFF; 8]),
+        ] {
+            let mut data = BytesMut::new();
+            position.write(&mut data);
+            assert_eq!(data[..], bytes, "{position:?}");
+
+            let mut data = Bytes::copy_from_slice(&bytes);
+            assert_eq!(Position::read(&mut data).unwrap(), position);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(x in -(1 << 25)..(1 << 25), y in -(1 << 11)..(1 << 11), z in -(1 << 25)..(1 << 25)) {
+            let mut data = BytesMut::new();
+            Position::new(x, y, z).write(&mut data);
+
+            let mut data = data.freeze();
+            prop_assert_eq!(Position::read(&mut data).unwrap(), Position::new(x, y, z));
+        }
+    }
+}
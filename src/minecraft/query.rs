@@ -0,0 +1,302 @@
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BufMut, BytesMut};
+use constcat::concat;
+use hmac::{Hmac, Mac};
+use rand::RngExt;
+use sha2::Sha256;
+
+use crate::{
+    ServerError,
+    config::MIN_SUPPORTED_VERSION,
+    status::{StatusProvider, StatusRequest},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Answers the [Query protocol](https://minecraft.wiki/w/Query) over UDP, used by server lists and monitoring tools
+///
+/// Polled from the server loop, so it never blocks.
+#[derive(Debug)]
+pub struct QueryListener {
+    socket: UdpSocket,
+    /// The port of the Minecraft server itself, reported as `hostport`
+    server_port: u16,
+    /// Challenges are derived from this and the client address, so one only works for the address it was sent to
+    secret: [u8; 32],
+    /// Still accepted for a while, so a client that just got its challenge doesn't fail
+    previous_secret: [u8; 32],
+    rotated: Instant,
+}
+
+impl QueryListener {
+    const MAGIC: [u8; 2] = [0xFE, 0xFD];
+    const HANDSHAKE: u8 = 9;
+    const STAT: u8 = 0;
+    const CHALLENGE_INTERVAL: Duration = Duration::from_secs(30);
+    /// Magic + type + session id + challenge
+    const BASIC_STAT_LEN: usize = 2 + 1 + 4 + 4;
+    /// A full stat request pads the challenge with 4 more bytes
+    const FULL_STAT_LEN: usize = Self::BASIC_STAT_LEN + 4;
+
+    pub fn bind(port: u16, server_port: u16) -> Result<Self, ServerError> {
+        let socket = UdpSocket::bind(format!("0.0.0.0:{port}"))
+            .and_then(|socket| {
+                socket.set_nonblocking(true)?;
+                Ok(socket)
+            })
+            .map_err(|e| ServerError::UdpBindError(port, e))?;
+
+        let secret = rand::rng().random();
+        Ok(Self {
+            socket,
+            server_port,
+            secret,
+            previous_secret: secret,
+            rotated: Instant::now(),
+        })
+    }
+
+    /// Answers every query that is waiting, malformed packets are ignored
+    pub fn poll<S: StatusProvider>(&mut self, status: &S) {
+        if self.rotated.elapsed() >= Self::CHALLENGE_INTERVAL {
+            self.previous_secret = self.secret;
+            self.secret = rand::rng().random();
+            self.rotated = Instant::now();
+        }
+
+        let mut buf = [0u8; 1460];
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => return,
+                // e.g. icmp port unreachable from a previous response on windows,
+                // whatever is left gets read on the next poll
+                Err(_) => return,
+            };
+
+            self.handle(&buf[..len], addr, status);
+        }
+    }
+
+    fn handle<S: StatusProvider>(&self, mut data: &[u8], addr: SocketAddr, status: &S) {
+        if data.len() < 2 + 1 + 4 || data[..2] != Self::MAGIC {
+            return;
+        }
+        let len = data.len();
+        data.advance(2);
+        let kind = data.get_u8();
+        let session = data.get_i32();
+
+        let response = match kind {
+            Self::HANDSHAKE => {
+                let mut res = Self::header(Self::HANDSHAKE, session);
+                res.extend_from_slice(challenge(&self.secret, addr).to_string().as_bytes());
+                res.put_u8(0);
+                res
+            }
+            Self::STAT if len == Self::BASIC_STAT_LEN || len == Self::FULL_STAT_LEN => {
+                let challenge = data.get_i32();
+                // a spoofed source address never got this challenge, so it can't be sent a stat
+                if challenge != self::challenge(&self.secret, addr)
+                    && challenge != self::challenge(&self.previous_secret, addr)
+                {
+                    return;
+                }
+
+                let stat = QueryStat::new(status, self.server_port);
+                match len == Self::FULL_STAT_LEN {
+                    true => stat.full(session),
+                    false => stat.basic(session),
+                }
+            }
+            _ => return,
+        };
+
+        let _ = self.socket.send_to(&response, addr);
+    }
+
+    fn header(kind: u8, session: i32) -> BytesMut {
+        let mut data = BytesMut::new();
+        data.put_u8(kind);
+        data.put_i32(session);
+        data
+    }
+}
+
+/// The challenge for a client address
+///
+/// Some clients parse the challenge as an unsigned number, so it is never negative.
+fn challenge(secret: &[u8; 32], addr: SocketAddr) -> i32 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("hmac accepts any key length");
+    match addr.ip() {
+        IpAddr::V4(ip) => mac.update(&ip.octets()),
+        IpAddr::V6(ip) => mac.update(&ip.octets()),
+    }
+    mac.update(&addr.port().to_be_bytes());
+
+    let tag = mac.finalize().into_bytes();
+    i32::from_be_bytes([tag[0], tag[1], tag[2], tag[3]]) & i32::MAX
+}
+
+/// What a stat response reports, taken from the [`StatusConfig`](crate::StatusConfig)
+struct QueryStat {
+    motd: String,
+    version: String,
+    online: i32,
+    max: i32,
+    players: Vec<String>,
+    port: u16,
+}
+
+impl QueryStat {
+    const GAME_TYPE: &str = "SMP";
+    const GAME_ID: &str = "MINECRAFT";
+    const MAP: &str = "world";
+    const HOST_IP: &str = "0.0.0.0";
+
+    fn new<S: StatusProvider>(status: &S, port: u16) -> Self {
+        // queries don't send a handshake, so there is nothing known about the client
+        let config = status.status(&StatusRequest::default());
+        let players = config.players.unwrap_or_default();
+
+        Self {
+            motd: config.description.map(|d| d.to_plain()).unwrap_or_default(),
+            version: config
+                .version_name
+                .unwrap_or_else(|| concat!(MIN_SUPPORTED_VERSION, "+").to_string()),
+            online: players.online,
+            max: players.max,
+            players: players.sample.into_iter().map(|p| p.username).collect(),
+            port,
+        }
+    }
+
+    fn basic(&self, session: i32) -> BytesMut {
+        let mut data = QueryListener::header(QueryListener::STAT, session);
+
+        for value in [
+            self.motd.as_str(),
+            Self::GAME_TYPE,
+            Self::MAP,
+            &self.online.to_string(),
+            &self.max.to_string(),
+        ] {
+            put_cstring(&mut data, value);
+        }
+        data.put_u16_le(self.port);
+        put_cstring(&mut data, Self::HOST_IP);
+
+        data
+    }
+
+    fn full(&self, session: i32) -> BytesMut {
+        let mut data = QueryListener::header(QueryListener::STAT, session);
+        data.extend_from_slice(b"splitnum\0\x80\0");
+
+        for (key, value) in [
+            ("hostname", self.motd.as_str()),
+            ("gametype", Self::GAME_TYPE),
+            ("game_id", Self::GAME_ID),
+            ("version", &self.version),
+            ("plugins", ""),
+            ("map", Self::MAP),
+            ("numplayers", &self.online.to_string()),
+            ("maxplayers", &self.max.to_string()),
+            ("hostport", &self.port.to_string()),
+            ("hostip", Self::HOST_IP),
+        ] {
+            put_cstring(&mut data, key);
+            put_cstring(&mut data, value);
+        }
+        data.put_u8(0);
+
+        data.extend_from_slice(b"\x01player_\0\0");
+        for player in &self.players {
+            put_cstring(&mut data, player);
+        }
+        data.put_u8(0);
+
+        data
+    }
+}
+
+/// Writes a null terminated string, null bytes inside it would end it early so they are dropped
+fn put_cstring(data: &mut BytesMut, value: &str) {
+    data.extend(value.bytes().filter(|b| *b != 0));
+    data.put_u8(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::StatusConfig;
+
+    use super::*;
+
+    fn client() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        socket
+    }
+
+    fn request(kind: u8, challenge: Option<i32>) -> Vec<u8> {
+        let mut data = BytesMut::new();
+        data.extend_from_slice(&QueryListener::MAGIC);
+        data.put_u8(kind);
+        data.put_i32(1);
+        if let Some(challenge) = challenge {
+            data.put_i32(challenge);
+        }
+        data.to_vec()
+    }
+
+    /// Sends a request through the listener, `None` if it wasn't answered
+    fn send(listener: &mut QueryListener, client: &UdpSocket, request: &[u8]) -> Option<Vec<u8>> {
+        let port = listener.socket.local_addr().unwrap().port();
+        client.send_to(request, ("127.0.0.1", port)).unwrap();
+        std::thread::sleep(Duration::from_millis(20));
+        listener.poll(&StatusConfig::default());
+
+        let mut buf = [0u8; 1460];
+        client.recv(&mut buf).ok().map(|len| buf[..len].to_vec())
+    }
+
+    #[test]
+    fn challenge_per_address() {
+        let secret = [7; 32];
+        let a = "127.0.0.1:5000".parse().unwrap();
+        let b = "127.0.0.1:5001".parse().unwrap();
+
+        assert_eq!(challenge(&secret, a), challenge(&secret, a));
+        assert_ne!(challenge(&secret, a), challenge(&secret, b));
+        assert_ne!(challenge(&secret, a), challenge(&[8; 32], a));
+        assert!(challenge(&secret, a) >= 0);
+    }
+
+    #[test]
+    fn stat_needs_own_challenge() {
+        let mut listener = QueryListener::bind(0, 25565).unwrap();
+        let (client, spoofer) = (client(), client());
+
+        let handshake = send(
+            &mut listener,
+            &client,
+            &request(QueryListener::HANDSHAKE, None),
+        )
+        .expect("handshake is answered");
+        let challenge: i32 = std::str::from_utf8(&handshake[5..handshake.len() - 1])
+            .unwrap()
+            .parse()
+            .unwrap();
+
+        let stat = request(QueryListener::STAT, Some(challenge));
+        assert!(send(&mut listener, &spoofer, &stat).is_none());
+        assert!(send(&mut listener, &client, &stat).is_some());
+    }
+}
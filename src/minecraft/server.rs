@@ -6,52 +6,130 @@ use std::{
     time::Duration,
 };
 
-use crossbeam::channel::{Receiver, TryRecvError};
+use crossbeam::channel::{Receiver, TryRecvError, bounded};
 use rsa::{RsaPrivateKey, RsaPublicKey};
 
 use crate::{
     ServerError,
     broadcast::Broadcast,
     channel_message::{ChannelMessage, MessageData},
-    config::ServerConfig,
-    message::MessageGenerator,
+    config::{ReissuePolicy, ServerConfig, VirtualHost},
+    message::{AnyMessageGenerator, MessageGenerator},
     minecraft::{
         auth::gen_rsa_key,
-        handshake::{Handshake, Intent},
-        intents::{self, legacy_ping},
+        bedrock::BedrockListener,
+        handshake::{Handshake, Intent, normalize_host},
+        intents::{self, legacy_ping, status::StatusCache},
         packet::{InitPacket, Packet, ReadPacketData},
+        query::QueryListener,
     },
-    token::TokenGenerator,
+    nonce::NonceRegistry,
+    status::{AnyStatusProvider, StatusProvider, StatusRequest},
+    token::{AnyTokenGenerator, TokenGenerator, storage::TokenStorage},
 };
 
 #[derive(Debug, Clone)]
-pub struct ConnectionState<T: TokenGenerator, M: MessageGenerator> {
+pub struct ConnectionState {
     pub public_key: Arc<RsaPublicKey>,
     pub private_key: Arc<RsaPrivateKey>,
     pub broadcast: Broadcast,
-    pub token: T,
-    pub message: M,
+    pub storage: TokenStorage,
+    pub nonces: Option<NonceRegistry>,
+    pub reissue: ReissuePolicy,
+    pub wait_for_locale: bool,
+    /// The hostname of the virtual host the client connected through
+    pub vhost: Option<String>,
+    pub status_cache: StatusCache,
+    pub token: AnyTokenGenerator,
+    pub message: AnyMessageGenerator,
 }
 
-pub fn start(
-    config: ServerConfig,
+impl ConnectionState {
+    /// Switches to the generators of the virtual host that matches a hostname best, if any
+    pub fn apply_vhost<'a>(
+        &mut self,
+        vhosts: &'a [VirtualHost],
+        host: &str,
+    ) -> Option<&'a VirtualHost> {
+        let (_, vhost) = vhosts
+            .iter()
+            .filter_map(|v| Some((v.specificity(host)?, v)))
+            .max_by_key(|(specificity, _)| *specificity)?;
+
+        self.vhost = Some(vhost.hostname.clone());
+        self.token = vhost.token.clone();
+        self.message = vhost.message.clone();
+
+        Some(vhost)
+    }
+}
+
+pub fn start<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
+    config: ServerConfig<T, M, S>,
     broadcast: Broadcast,
+    storage: TokenStorage,
+    nonces: Option<NonceRegistry>,
     client_comm: Receiver<ChannelMessage>,
 ) -> () {
     let listener = TcpListener::bind(format!("0.0.0.0:{}", config.port)).unwrap();
     listener.set_nonblocking(true).unwrap();
 
-    let (priv_key, pub_key) = gen_rsa_key();
+    let (private_key, public_key) = gen_rsa_key();
+    let config = Arc::new(config);
+    let state = ConnectionState {
+        public_key,
+        private_key,
+        broadcast: broadcast.clone(),
+        storage,
+        nonces,
+        reissue: config.reissue,
+        wait_for_locale: config.wait_for_locale,
+        vhost: None,
+        status_cache: StatusCache::default(),
+        token: Arc::new(config.token.clone()),
+        message: Arc::new(config.message.clone()),
+    };
+    let status: AnyStatusProvider = Arc::new(config.status.clone());
+    let mut query =
+        config
+            .query_port
+            .and_then(|port| match QueryListener::bind(port, config.port) {
+                Ok(query) => Some(query),
+                Err(e) => {
+                    broadcast.send(ChannelMessage::new(MessageData::ConnectionError(Box::new(
+                        e,
+                    ))));
+                    None
+                }
+            });
+    // closing the sender stops the bedrock thread
+    let (stop_bedrock, bedrock_stopped) = bounded::<()>(0);
+    let bedrock = config.bedrock_port.and_then(|port| {
+        match BedrockListener::bind(port, config.bedrock_login) {
+            Ok(bedrock) => Some(bedrock.spawn(config.clone(), state.clone(), bedrock_stopped)),
+            // the java server keeps running without it
+            Err(e) => {
+                broadcast.send(ChannelMessage::new(MessageData::ConnectionError(Box::new(
+                    e,
+                ))));
+                None
+            }
+        }
+    });
 
     loop {
         match listener.accept() {
             Ok((stream, _)) => {
-                accept_connection(stream, &config, &broadcast, (&priv_key, &pub_key));
+                accept_connection(stream, &config, &state, &status);
             }
             Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
             Err(e) => panic!("{e:?}"),
         }
 
+        if let Some(query) = &mut query {
+            query.poll(&config.status);
+        }
+
         match client_comm.try_recv() {
             Ok(msg) => match msg.data {
                 MessageData::CloseServer => {
@@ -68,28 +146,28 @@ pub fn start(
         sleep(Duration::from_millis(10));
     }
 
+    drop(stop_bedrock);
+    if let Some(bedrock) = bedrock {
+        let _ = bedrock.join();
+    }
+
     // at the end broadcast a close server, this server itself doesnt listen
     // to the broadcast for this signal, but rather this closes all event listener threads
     broadcast.send(ChannelMessage::new(MessageData::CloseServer));
 }
 
-fn accept_connection(
+fn accept_connection<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
     mut stream: TcpStream,
-    config: &ServerConfig,
-    broadcast: &Broadcast,
-    (priv_key, pub_key): (&Arc<RsaPrivateKey>, &Arc<RsaPublicKey>),
+    config: &Arc<ServerConfig<T, M, S>>,
+    state: &ConnectionState,
+    status: &AnyStatusProvider,
 ) {
     stream.set_nonblocking(false).unwrap();
     stream.set_nodelay(true).unwrap();
 
-    let state = ConnectionState {
-        public_key: pub_key.clone(),
-        private_key: priv_key.clone(),
-        broadcast: broadcast.clone(),
-        token: config.token.clone(),
-        message: config.message.clone(),
-    };
-    let status_config = config.status.clone();
+    let mut state = state.clone();
+    let config = config.clone();
+    let mut status = status.clone();
 
     spawn(move || {
         let _bc = state.broadcast.clone();
@@ -99,29 +177,44 @@ fn accept_connection(
             // 1. Handshake
             let packet = Packet::read_init(&mut stream)?;
             match packet {
-                InitPacket::V1_6 => legacy_ping::_1dot6::advance(&mut stream, status_config)?,
-                InitPacket::V1_4To1_5 => {
-                    legacy_ping::_1dot4_to_1dot5::advance(&mut stream, status_config)?
-                }
-                InitPacket::Vbeta1_8To1_3 => {
-                    legacy_ping::beta1dot8_to_1dot3::advance(&mut stream, status_config)?
+                InitPacket::V1_6(ping) => {
+                    if let Some(vhost) =
+                        state.apply_vhost(&config.vhosts, &normalize_host(&ping.hostname))
+                    {
+                        status = vhost.status.clone();
+                    }
+
+                    legacy_ping::_1dot6::advance(
+                        &mut stream,
+                        status.status(&StatusRequest::from(&ping)),
+                        Some(&ping),
+                    )?
                 }
+                InitPacket::V1_4To1_5 => legacy_ping::_1dot4_to_1dot5::advance(
+                    &mut stream,
+                    status.status(&StatusRequest::legacy()),
+                )?,
+                InitPacket::Vbeta1_8To1_3 => legacy_ping::beta1dot8_to_1dot3::advance(
+                    &mut stream,
+                    status.status(&StatusRequest::legacy()),
+                )?,
                 InitPacket::V1_7Above(mut packet) => {
                     let handshake = Handshake::read(&mut packet.data)?;
 
+                    if let Some(vhost) = state.apply_vhost(&config.vhosts, &handshake.hostname()) {
+                        status = vhost.status.clone();
+                    }
+
                     match handshake.intent {
                         Intent::Status => {
-                            intents::status::advance(&mut stream, state, handshake, status_config)?
+                            intents::status::advance(&mut stream, state, handshake, &status)?
                         }
                         Intent::Login => {
-                            intents::login::advance(&mut stream, state, handshake, status_config)?
+                            intents::login::advance(&mut stream, state, handshake, &status)?
+                        }
+                        Intent::Transfer => {
+                            intents::transfer::advance(&mut stream, state, handshake, &status)?
                         }
-                        Intent::Transfer => intents::transfer::advance(
-                            &mut stream,
-                            state,
-                            handshake,
-                            status_config,
-                        )?,
                         Intent::Unknown(intent) => {
                             return Err(ServerError::UnknownHandshakeIntent(intent));
                         }
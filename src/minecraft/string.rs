@@ -3,13 +3,20 @@ use bytes::{Buf, Bytes};
 use crate::{
     error::TypeError,
     minecraft::{
-        packet::{ReadPacketData, WritePacketData},
+        packet::{ReadPacketData, WritePacketData, check_remaining},
         var_int::VarInt,
     },
 };
 
+/// The longest string the protocol allows, in UTF-16 code units
+pub const MAX_STRING_LENGTH: usize = 32767;
+
+/// A string prefixed with its length in bytes, at most `MAX` UTF-16 code units long
+///
+/// Only reading checks `MAX`, since clients may send anything but we know what we write.  
+/// Bounded strings are built with the tuple constructor, like `PacketString::<16>(name)`.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct PacketString(pub String);
+pub struct PacketString<const MAX: usize = MAX_STRING_LENGTH>(pub String);
 
 impl PacketString {
     pub fn new(str: impl ToString) -> Self {
@@ -17,18 +24,105 @@ impl PacketString {
     }
 }
 
-impl ReadPacketData for PacketString {
-    fn read(data: &mut Bytes) -> Result<PacketString, TypeError> {
-        let len = VarInt::read(data)?.0 as usize;
-        let content = data.copy_to_bytes(len);
-        Ok(PacketString(String::from_utf8(content.to_vec())?))
+impl<const MAX: usize> ReadPacketData for PacketString<MAX> {
+    fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+        let len = VarInt::read(data)?.0;
+        if len < 0 {
+            return Err(TypeError::InvalidLength(len));
+        }
+        // a UTF-16 code unit takes at most 3 bytes in UTF-8
+        if len as usize > MAX * 3 {
+            return Err(TypeError::StringTooLong(len as usize, MAX * 3));
+        }
+        check_remaining(data, len as usize)?;
+
+        let content = String::from_utf8(data.copy_to_bytes(len as usize).to_vec())?;
+        let units = content.encode_utf16().count();
+        if units > MAX {
+            return Err(TypeError::StringTooLong(units, MAX));
+        }
+
+        Ok(PacketString(content))
     }
 }
 
-impl WritePacketData for PacketString {
+impl<const MAX: usize> WritePacketData for PacketString<MAX> {
     fn write(self, data: &mut bytes::BytesMut) {
         let bytes = self.0.as_bytes();
         VarInt(bytes.len() as i32).write(data);
         data.extend_from_slice(&bytes);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use proptest::prelude::*;
+
+    use crate::{
+        error::TypeError,
+        minecraft::packet::{ReadPacketData, WritePacketData},
+    };
+
+    use super::PacketString;
+
+    #[test]
+    fn fixture() {
+        let mut data = BytesMut::new();
+        PacketString::new("héllo").write(&mut data);
+        // the length is in bytes, not characters
+        assert_eq!(&data[..], b"\x06h\xC3\xA9llo");
+
+        let mut data = Bytes::from_static(b"\x06h\xC3\xA9llo");
+        let read: PacketString = PacketString::read(&mut data).unwrap();
+        assert_eq!(read.0, "héllo");
+    }
+
+    #[test]
+    fn bounded() {
+        // 5 code units in 6 bytes
+        let mut data = Bytes::from_static(b"\x06h\xC3\xA9llo");
+        assert_eq!(PacketString::<5>::read(&mut data).unwrap().0, "héllo");
+
+        let mut data = Bytes::from_static(b"\x06h\xC3\xA9llo");
+        assert!(matches!(
+            PacketString::<4>::read(&mut data),
+            Err(TypeError::StringTooLong(5, 4))
+        ));
+
+        // rejected before reading, a 16 character name can't take more than 48 bytes
+        let mut data = Bytes::from_static(b"\x31");
+        assert!(matches!(
+            PacketString::<16>::read(&mut data),
+            Err(TypeError::StringTooLong(49, 48))
+        ));
+    }
+
+    #[test]
+    fn malformed() {
+        let mut data = Bytes::from_static(b"\x05abc");
+        assert!(matches!(
+            PacketString::<16>::read(&mut data),
+            Err(TypeError::UnexpectedEnd(5, 3))
+        ));
+
+        let mut data = Bytes::from_static(b"\xFF\xFF\xFF\xFF\x0F");
+        assert!(matches!(
+            PacketString::<16>::read(&mut data),
+            Err(TypeError::InvalidLength(-1))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(value: String) {
+            let mut data = BytesMut::new();
+            PacketString::new(&value).write(&mut data);
+
+            let mut data = data.freeze();
+            let read: PacketString = PacketString::read(&mut data).unwrap();
+            prop_assert_eq!(read.0, value);
+            prop_assert!(data.is_empty());
+        }
+    }
+}
@@ -1,21 +1,21 @@
-use bytes::BytesMut;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use uuid::Uuid;
 
-use crate::minecraft::{packet::WritePacketData, protocol_version, string::PacketString};
+use crate::{
+    error::TypeError,
+    minecraft::packet::{ReadPacketData, WritePacketData, check_remaining},
+};
 
-// https://github.com/Quozul/PicoLimbo/blob/fcb126585798f78a58d39defde2bac694b132ccb/crates/minecraft_protocol/src/data_types/uuid.rs#L47
-pub fn uuid_to_bytes(uuid: Uuid, protocol_version: i32) -> Vec<u8> {
-    match protocol_version {
-        protocol_version::V1_16.. => uuid.as_bytes().to_vec(),
-        protocol_version::V1_7_6.. => {
-            let mut data = BytesMut::new();
-            PacketString::new(uuid.as_hyphenated()).write(&mut data);
-            data.to_vec()
-        }
-        _ => {
-            let mut data = BytesMut::new();
-            PacketString::new(uuid.as_simple()).write(&mut data);
-            data.to_vec()
-        }
+/// As sent since 1.16, 16 bytes with the most significant first
+impl ReadPacketData for Uuid {
+    fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+        check_remaining(data, 16)?;
+        Ok(Uuid::from_u128(data.get_u128()))
+    }
+}
+
+impl WritePacketData for Uuid {
+    fn write(self, data: &mut BytesMut) {
+        data.put_u128(self.as_u128());
     }
 }
@@ -6,7 +6,7 @@ use crate::{
     error::TypeError,
     minecraft::{
         encrypt::{Aes128CfbDec, decrypt_packet},
-        packet::{ReadPacketData, WritePacketData},
+        packet::{ReadPacketData, WritePacketData, check_remaining},
     },
 };
 
@@ -84,6 +84,7 @@ impl ReadPacketData for VarInt {
         let mut position: i32 = 0;
 
         while position < 32 {
+            check_remaining(data, 1)?;
             let byte = data.get_u8();
             value |= ((byte & Self::SEGMENT_BITS) as i32) << position;
 
@@ -115,3 +116,53 @@ impl WritePacketData for VarInt {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use proptest::prelude::*;
+
+    use crate::minecraft::packet::{ReadPacketData, WritePacketData};
+
+    use super::VarInt;
+
+    const FIXTURES: [(i32, &[u8]); 6] = [
+        (0, &[0x00]),
+        (1, &[0x01]),
+        (127, &[0x7F]),
+        (128, &[0x80, 0x01]),
+        (-1, &[0xFF, 0xFF, 0xFF, 0xFF, 0x0F]),
+        (i32::MAX, &[0xFF, 0xFF, 0xFF, 0xFF, 0x07]),
+    ];
+
+    #[test]
+    fn write_fixtures() {
+        for (value, expected) in FIXTURES {
+            let mut data = BytesMut::new();
+            VarInt(value).write(&mut data);
+            assert_eq!(&data[..], expected, "{value}");
+        }
+    }
+
+    #[test]
+    fn read_fixtures() {
+        for (value, bytes) in FIXTURES {
+            let mut data = Bytes::from_static(bytes);
+            assert_eq!(VarInt::read(&mut data).unwrap(), VarInt(value));
+            assert!(data.is_empty());
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(value: i32) {
+            let mut data = BytesMut::new();
+            VarInt(value).write(&mut data);
+            prop_assert!(data.len() <= 5);
+
+            let mut data = data.freeze();
+            prop_assert_eq!(VarInt::read(&mut data).unwrap(), VarInt(value));
+            prop_assert!(data.is_empty());
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::{
+    error::TypeError,
+    minecraft::packet::{ReadPacketData, WritePacketData, check_remaining},
+};
+
+/// Same encoding as [`VarInt`](super::var_int::VarInt), but up to 10 bytes long
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(not(test), allow(dead_code))] // no packet we handle has one yet
+pub(crate) struct VarLong(pub i64);
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl VarLong {
+    const SEGMENT_BITS: u8 = 0x7F;
+    const CONTINUE_BIT: u8 = 0x80;
+}
+
+impl ReadPacketData for VarLong {
+    fn read(data: &mut Bytes) -> Result<Self, TypeError> {
+        let mut value: i64 = 0;
+        let mut position: i32 = 0;
+
+        while position < 64 {
+            check_remaining(data, 1)?;
+            let byte = data.get_u8();
+            value |= ((byte & Self::SEGMENT_BITS) as i64) << position;
+
+            if (byte & Self::CONTINUE_BIT) == 0 {
+                return Ok(VarLong(value));
+            }
+
+            position += 7;
+        }
+
+        Err(TypeError::OversizedVarLong(value))
+    }
+}
+
+impl WritePacketData for VarLong {
+    fn write(self, data: &mut BytesMut) {
+        let mut value = self.0 as u64;
+
+        loop {
+            if (value & !(Self::SEGMENT_BITS as u64)) == 0 {
+                data.put_u8(value as u8);
+                return;
+            } else {
+                data.put_u8(
+                    ((value & (Self::SEGMENT_BITS as u64)) | (Self::CONTINUE_BIT as u64)) as u8,
+                );
+                value >>= 7;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+    use proptest::prelude::*;
+
+    use crate::{
+        error::TypeError,
+        minecraft::packet::{ReadPacketData, WritePacketData},
+    };
+
+    use super::VarLong;
+
+    const FIXTURES: [(i64, &[u8]); 5] = [
+        (0, &[0x00]),
+        (2147483648, &[0x80, 0x80, 0x80, 0x80, 0x08]),
+        (
+            i64::MAX,
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x7F],
+        ),
+        (
+            -1,
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01],
+        ),
+        (
+            i64::MIN,
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01],
+        ),
+    ];
+
+    #[test]
+    fn fixtures() {
+        for (value, bytes) in FIXTURES {
+            let mut data = BytesMut::new();
+            VarLong(value).write(&mut data);
+            assert_eq!(&data[..], bytes, "{value}");
+
+            assert_eq!(
+                VarLong::read(&mut Bytes::from_static(bytes)).unwrap(),
+                VarLong(value)
+            );
+        }
+    }
+
+    #[test]
+    fn oversized() {
+        let mut data = Bytes::from_static(&[0xFF; 11]);
+        assert!(matches!(
+            VarLong::read(&mut data),
+            Err(TypeError::OversizedVarLong(_))
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut data = Bytes::from_static(&[0xFF, 0xFF]);
+        assert!(matches!(
+            VarLong::read(&mut data),
+            Err(TypeError::UnexpectedEnd(1, 0))
+        ));
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(value: i64) {
+            let mut data = BytesMut::new();
+            VarLong(value).write(&mut data);
+            prop_assert!(data.len() <= 10);
+
+            let mut data = data.freeze();
+            prop_assert_eq!(VarLong::read(&mut data).unwrap(), VarLong(value));
+            prop_assert!(data.is_empty());
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::{MCHAError, config::NonceBinding};
+
+/// Nonces registered by web sessions, mapped to when they were registered
+#[derive(Debug, Clone)]
+pub(crate) struct NonceRegistry {
+    binding: NonceBinding,
+    ttl: Duration,
+    nonces: Arc<Mutex<HashMap<String, DateTime<Utc>>>>,
+}
+
+impl NonceRegistry {
+    const MAX_LABEL_LEN: usize = 63;
+
+    pub fn new(binding: NonceBinding, ttl: Duration) -> Self {
+        Self {
+            binding,
+            ttl,
+            nonces: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn binding(&self) -> &NonceBinding {
+        &self.binding
+    }
+
+    /// Registers a nonce and returns the hostname the player should join with
+    pub fn register(&self, nonce: &str) -> Result<String, MCHAError> {
+        let nonce = nonce.to_lowercase();
+
+        // the nonce becomes a dns label, so it has to be a valid one
+        let valid = !nonce.is_empty()
+            && nonce.len() <= Self::MAX_LABEL_LEN
+            && !nonce.starts_with('-')
+            && !nonce.ends_with('-')
+            && nonce
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid {
+            return Err(MCHAError::InvalidNonce(nonce));
+        }
+
+        let mut lock = self.nonces.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Utc::now();
+
+        // registering is rare enough that we can just prune old nonces here
+        lock.retain(|_, time| now <= self.expires(*time));
+        lock.insert(nonce.clone(), now);
+
+        Ok(format!("{nonce}.{}", self.binding.domain))
+    }
+
+    /// Extracts a registered nonce from a hostname, see [`Handshake::hostname`](crate::minecraft::handshake::Handshake::hostname)
+    pub fn extract(&self, host: &str) -> Option<String> {
+        let suffix = format!(".{}", self.binding.domain.to_lowercase());
+        let nonce = host.strip_suffix(&suffix)?;
+        if nonce.contains('.') {
+            return None;
+        }
+
+        let lock = self.nonces.lock().unwrap_or_else(|e| e.into_inner());
+        match lock.get(nonce) {
+            Some(time) if Utc::now() <= self.expires(*time) => Some(nonce.to_string()),
+            _ => None,
+        }
+    }
+
+    /// When a nonce registered at `time` expires, a ttl too long for a date never expires
+    fn expires(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        TimeDelta::from_std(self.ttl)
+            .ok()
+            .and_then(|ttl| time.checked_add_signed(ttl))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_and_extract() {
+        let registry = NonceRegistry::new(NonceBinding::new("auth.example.com"), Duration::MAX);
+
+        let host = registry.register("Abc-123").unwrap();
+        assert_eq!(host, "abc-123.auth.example.com");
+        assert_eq!(registry.extract(&host).as_deref(), Some("abc-123"));
+        // registering prunes with the same ttl
+        registry.register("other").unwrap();
+        assert!(registry.extract(&host).is_some());
+
+        assert!(registry.extract("unknown.auth.example.com").is_none());
+        assert!(registry.extract("a.abc-123.auth.example.com").is_none());
+    }
+
+    #[test]
+    fn invalid_nonce() {
+        let registry = NonceRegistry::new(NonceBinding::new("auth.example.com"), Duration::MAX);
+
+        for nonce in ["", "-abc", "abc-", "a.b", &"a".repeat(64)] {
+            assert!(matches!(
+                registry.register(nonce),
+                Err(MCHAError::InvalidNonce(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BufMut, BytesMut};
+use chrono::Utc;
+
+use crate::{
+    error::{PingError, TypeError},
+    minecraft::{
+        handshake::{Handshake, Intent},
+        packet::{Packet, ReadPacketData, State, WritePacketData},
+        protocol_version,
+        string::PacketString,
+        var_int::VarInt,
+    },
+    status::StatusResponse,
+};
+
+// https://minecraft.wiki/w/Java_Edition_protocol/Server_List_Ping
+
+const DEFAULT_PORT: u16 = 25565;
+/// For connecting and for every read and write after that
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// The newest protocol this crate knows, so the status comes in its newest format
+const PROTOCOL: i32 = protocol_version::V1_21_5;
+/// The protocol of 1.6.4, the last version with this ping
+const LEGACY_PROTOCOL: u8 = 78;
+
+/// A status response and how long the server took to answer
+#[derive(Debug, Clone)]
+pub struct Ping {
+    pub status: StatusResponse,
+    /// Round trip of the ping after the status, like the server list shows it
+    pub latency: Duration,
+}
+
+/// Which pre 1.7 ping to send, see [`ping_legacy`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyPingKind {
+    /// The ping of 1.6, which 1.4 and up answer with their version
+    V1_6,
+    /// The ping of beta 1.8 to 1.3, answered with only the motd and player counts
+    Beta,
+}
+
+/// What a server answers a pre 1.7 ping with
+#[derive(Debug, Clone)]
+pub struct LegacyPing {
+    /// `None` for [`LegacyPingKind::Beta`]
+    pub protocol: Option<i32>,
+    /// `None` for [`LegacyPingKind::Beta`]
+    pub version: Option<String>,
+    /// May contain `§` formatting codes, see [`TextComponent::from_legacy`](crate::TextComponent::from_legacy)
+    pub motd: String,
+    pub online: i32,
+    pub max: i32,
+    /// From sending the ping to receiving the response
+    pub latency: Duration,
+}
+
+/// Pings a server like the server list does, to check that it is up and answers correctly
+///
+/// `addr` is a hostname or ip, with an optional port that defaults to `25565`.
+/// SRV records aren't looked up. Every step times out after 5 seconds.
+///
+/// ## Example
+/// ```no_run
+/// # use mc_headless_auth::ping;
+/// let ping = ping("localhost:25565").unwrap();
+/// println!("{} in {:?}", ping.status.version.name, ping.latency);
+/// ```
+pub fn ping(addr: &str) -> Result<Ping, PingError> {
+    let (host, port) = split_addr(addr);
+    let mut stream = connect(host, port)?;
+
+    let mut handshake = BytesMut::new();
+    Handshake {
+        protocol_version: VarInt(PROTOCOL),
+        server_address: PacketString::new(host),
+        server_port: port,
+        intent: Intent::Status,
+    }
+    .write(&mut handshake);
+    Packet::new(0x00, handshake.into()).write_stream(&mut stream)?;
+    Packet::new(0x00, Default::default()).write_stream(&mut stream)?;
+
+    let mut response = Packet::from_stream(&mut stream, State::Status, 0x00)?;
+    let status: PacketString = PacketString::read(&mut response.data)?;
+    let status = serde_json::from_str(&status.0)?;
+
+    let payload = Utc::now().timestamp_millis();
+    let mut data = BytesMut::new();
+    data.put_i64(payload);
+
+    let start = Instant::now();
+    Packet::new(0x01, data.into()).write_stream(&mut stream)?;
+    let mut pong = Packet::from_stream(&mut stream, State::Status, 0x01)?;
+    let latency = start.elapsed();
+
+    if pong.data.remaining() < 8 {
+        return Err(PingError::InvalidResponse("pong is too short"));
+    }
+    let received = pong.data.get_i64();
+    if received != payload {
+        return Err(PingError::MismatchedPong(payload, received));
+    }
+
+    Ok(Ping { status, latency })
+}
+
+/// Pings a server like clients before 1.7 did
+///
+/// Modern servers still answer these, so this also checks that old clients see the right thing.
+/// `addr` is the same as for [`ping`].
+pub fn ping_legacy(addr: &str, kind: LegacyPingKind) -> Result<LegacyPing, PingError> {
+    let (host, port) = split_addr(addr);
+    let request = legacy_request(host, port, kind)?;
+    let mut stream = connect(host, port)?;
+
+    let start = Instant::now();
+    stream.write_all(&request).map_err(TypeError::WriteError)?;
+    let response = read_kick(&mut stream)?;
+    let latency = start.elapsed();
+
+    parse_legacy(&response, kind, latency)
+}
+
+/// The bytes of a pre 1.7 ping
+fn legacy_request(host: &str, port: u16, kind: LegacyPingKind) -> Result<BytesMut, PingError> {
+    let mut data = BytesMut::new();
+    data.put_u8(0xFE);
+    if kind == LegacyPingKind::V1_6 {
+        // protocol, host length and host, port
+        let host_units = host.encode_utf16().count();
+        let len = host_units
+            .checked_mul(2)
+            .and_then(|len| len.checked_add(1 + 2 + 4))
+            .and_then(|len| u16::try_from(len).ok())
+            .ok_or(PingError::HostTooLong(host_units))?;
+
+        data.put_u8(0x01);
+        // plugin message with the host that was pinged
+        data.put_u8(0xFA);
+        put_utf16(&mut data, "MC|PingHost");
+        data.put_u16(len);
+        data.put_u8(LEGACY_PROTOCOL);
+        put_utf16(&mut data, host);
+        data.put_i32(port as i32);
+    }
+
+    Ok(data)
+}
+
+/// Reads the fields of the kick message a pre 1.7 ping is answered with
+fn parse_legacy(
+    response: &str,
+    kind: LegacyPingKind,
+    latency: Duration,
+) -> Result<LegacyPing, PingError> {
+    let number = |value: Option<&str>, error: &'static str| {
+        value
+            .and_then(|v| v.parse().ok())
+            .ok_or(PingError::InvalidResponse(error))
+    };
+    let count = |value| number(value, "player count is not a number");
+
+    match kind {
+        LegacyPingKind::V1_6 => {
+            let mut fields = response
+                .strip_prefix("§1\0")
+                .ok_or(PingError::InvalidResponse("missing §1 prefix"))?
+                .split('\0');
+
+            Ok(LegacyPing {
+                protocol: Some(number(fields.next(), "protocol is not a number")?),
+                version: fields.next().map(str::to_string),
+                motd: fields.next().unwrap_or_default().to_string(),
+                online: count(fields.next())?,
+                max: count(fields.next())?,
+                latency,
+            })
+        }
+        LegacyPingKind::Beta => {
+            // the motd can't contain `§`, so the counts are the last two fields
+            let mut fields = response.rsplitn(3, '§');
+            let max = count(fields.next())?;
+            let online = count(fields.next())?;
+
+            Ok(LegacyPing {
+                protocol: None,
+                version: None,
+                motd: fields.next().unwrap_or_default().to_string(),
+                online,
+                max,
+                latency,
+            })
+        }
+    }
+}
+
+/// Splits an optional port off, ipv6 addresses need brackets to have one like `[::1]:25565`
+fn split_addr(addr: &str) -> (&str, u16) {
+    let (host, port) = match addr.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') || host.ends_with(']') => match port.parse() {
+            Ok(port) => (host, port),
+            Err(_) => (addr, DEFAULT_PORT),
+        },
+        _ => (addr, DEFAULT_PORT),
+    };
+
+    (host.trim_start_matches('[').trim_end_matches(']'), port)
+}
+
+fn connect(host: &str, port: u16) -> Result<TcpStream, PingError> {
+    let mut last_error = io::Error::new(
+        ErrorKind::NotFound,
+        format!("{host} did not resolve to an address"),
+    );
+
+    for addr in (host, port)
+        .to_socket_addrs()
+        .map_err(PingError::ConnectError)?
+    {
+        match TcpStream::connect_timeout(&addr, TIMEOUT) {
+            Ok(stream) => {
+                stream
+                    .set_read_timeout(Some(TIMEOUT))
+                    .map_err(PingError::ConnectError)?;
+                stream
+                    .set_write_timeout(Some(TIMEOUT))
+                    .map_err(PingError::ConnectError)?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(PingError::ConnectError(last_error))
+}
+
+/// Writes a string with its length in UTF-16 code units, as legacy packets have them
+fn put_utf16(data: &mut BytesMut, value: &str) {
+    data.put_u16(value.encode_utf16().count() as u16);
+    for unit in value.encode_utf16() {
+        data.put_u16(unit);
+    }
+}
+
+/// Reads the kick packet legacy pings are answered with
+fn read_kick(stream: &mut TcpStream) -> Result<String, PingError> {
+    let mut header = [0u8; 3];
+    stream
+        .read_exact(&mut header)
+        .map_err(TypeError::ReadError)?;
+    if header[0] != 0xFF {
+        return Err(PingError::InvalidResponse("not a kick packet"));
+    }
+
+    let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+    let mut data = vec![0u8; len * 2];
+    stream.read_exact(&mut data).map_err(TypeError::ReadError)?;
+
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+        .collect();
+    String::from_utf16(&units).map_err(|_| PingError::InvalidResponse("invalid UTF-16"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_addr_ports() {
+        assert_eq!(split_addr("localhost"), ("localhost", 25565));
+        assert_eq!(
+            split_addr("play.example.com:25566"),
+            ("play.example.com", 25566)
+        );
+        assert_eq!(split_addr("127.0.0.1:1"), ("127.0.0.1", 1));
+    }
+
+    #[test]
+    fn split_addr_ipv6() {
+        assert_eq!(split_addr("[::1]:25566"), ("::1", 25566));
+        assert_eq!(split_addr("[::1]"), ("::1", 25565));
+        // without brackets the last group isn't a port
+        assert_eq!(split_addr("::1"), ("::1", 25565));
+        assert_eq!(split_addr("2001:db8::1"), ("2001:db8::1", 25565));
+    }
+
+    #[test]
+    fn split_addr_invalid_port() {
+        assert_eq!(split_addr("localhost:"), ("localhost:", 25565));
+        assert_eq!(split_addr("localhost:99999"), ("localhost:99999", 25565));
+    }
+
+    #[test]
+    fn legacy_request_1_6() {
+        let request = legacy_request("localhost", 25565, LegacyPingKind::V1_6).unwrap();
+
+        let mut expected = BytesMut::new();
+        expected.extend_from_slice(b"\xFE\x01\xFA");
+        put_utf16(&mut expected, "MC|PingHost");
+        expected.extend_from_slice(&(7u16 + 2 * 9).to_be_bytes());
+        expected.put_u8(LEGACY_PROTOCOL);
+        put_utf16(&mut expected, "localhost");
+        expected.put_i32(25565);
+        assert_eq!(request, expected);
+
+        let request = legacy_request("localhost", 25565, LegacyPingKind::Beta).unwrap();
+        assert_eq!(&request[..], b"\xFE");
+    }
+
+    #[test]
+    fn legacy_request_long_host() {
+        let host = "a".repeat(u16::MAX as usize);
+
+        assert!(matches!(
+            legacy_request(&host, 25565, LegacyPingKind::V1_6),
+            Err(PingError::HostTooLong(65535))
+        ));
+        assert!(legacy_request(&host, 25565, LegacyPingKind::Beta).is_ok());
+    }
+
+    #[test]
+    fn parse_1_6() {
+        let response = "§1\x0078\x001.6.4\0§aA Minecraft Server\x005\x0020";
+        let ping = parse_legacy(response, LegacyPingKind::V1_6, Duration::ZERO).unwrap();
+
+        assert_eq!(ping.protocol, Some(78));
+        assert_eq!(ping.version.as_deref(), Some("1.6.4"));
+        assert_eq!(ping.motd, "§aA Minecraft Server");
+        assert_eq!((ping.online, ping.max), (5, 20));
+    }
+
+    #[test]
+    fn parse_beta() {
+        let response = "A Minecraft Server§5§20";
+        let ping = parse_legacy(response, LegacyPingKind::Beta, Duration::ZERO).unwrap();
+
+        assert_eq!(ping.protocol, None);
+        assert_eq!(ping.version, None);
+        assert_eq!(ping.motd, "A Minecraft Server");
+        assert_eq!((ping.online, ping.max), (5, 20));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        let invalid = |response, kind| match parse_legacy(response, kind, Duration::ZERO) {
+            Err(PingError::InvalidResponse(error)) => error,
+            other => panic!("expected an invalid response, got {other:?}"),
+        };
+
+        assert_eq!(
+            invalid("78\x001.6.4\0motd\x005\x0020", LegacyPingKind::V1_6),
+            "missing §1 prefix"
+        );
+        assert_eq!(
+            invalid("§1\0new\x001.6.4\0motd\x005\x0020", LegacyPingKind::V1_6),
+            "protocol is not a number"
+        );
+        assert_eq!(
+            invalid("§1\x0078\x001.6.4\0motd\0five\x0020", LegacyPingKind::V1_6),
+            "player count is not a number"
+        );
+        assert_eq!(
+            invalid("motd§5", LegacyPingKind::Beta),
+            "player count is not a number"
+        );
+    }
+}
@@ -7,6 +7,10 @@ use crate::{MCHAError, minecraft::login_start::LoginStart};
 pub struct Player {
     pub uuid: Uuid,
     pub username: String,
+    /// The hostname of the [`VirtualHost`](crate::VirtualHost) the player joined through, if any
+    pub vhost: Option<String>,
+    /// The Xbox user id of a Bedrock Edition player, `None` for Java Edition players
+    pub xuid: Option<String>,
 }
 
 impl TryFrom<LoginStart> for Player {
@@ -16,6 +20,8 @@ impl TryFrom<LoginStart> for Player {
         Ok(Self {
             uuid: value.uuid.ok_or(MCHAError::NoUuid(value.name.0.clone()))?,
             username: value.name.0,
+            vhost: None,
+            xuid: None,
         })
     }
 }
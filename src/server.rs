@@ -1,18 +1,27 @@
 use std::{
+    collections::HashMap,
     sync::{Arc, Mutex},
     thread::{self, JoinHandle},
 };
 
 use crossbeam::channel::{Sender, bounded};
+use uuid::Uuid;
 
 use crate::{
     MCHAError, ServerError,
     broadcast::Broadcast,
     channel_message::{ChannelMessage, MessageData},
     config::ServerConfig,
+    message::MessageGenerator,
     minecraft,
+    nonce::NonceRegistry,
     player::Player,
-    token::storage::TokenStorage,
+    signed_token::SignedToken,
+    status::StatusProvider,
+    token::{
+        TokenGenerator,
+        storage::{Claim, TokenKey, TokenStorage},
+    },
 };
 
 /// The consumer end of the Minecraft server  
@@ -20,20 +29,125 @@ use crate::{
 /// Holds an internal connection to the thread running the Minecraft server.  
 ///
 /// Use this to [`start`](Server::start) it and get events from it.  
+/// Clones share the same server, which shuts down once the last clone is dropped.  
 #[derive(Clone)]
 pub struct Server {
     pub(crate) broadcast: Broadcast,
     pub(crate) storage: TokenStorage,
-    pub(crate) server_signal: Sender<ChannelMessage>,
-    pub(crate) server_handle: Arc<Mutex<Option<JoinHandle<()>>>>,
+    pub(crate) nonces: Option<NonceRegistry>,
+    /// The default namespace under `None` and one for every virtual host
+    pub(crate) namespaces: Arc<HashMap<Option<String>, Namespace>>,
+    pub(crate) thread: Arc<ServerThread>,
 }
 
-impl Drop for Server {
+/// The thread running the Minecraft server, shared by all clones of a [`Server`]
+///
+/// Shuts the server down once the last clone is dropped.  
+pub(crate) struct ServerThread {
+    signal: Sender<ChannelMessage>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+    storage: TokenStorage,
+}
+
+impl ServerThread {
+    fn shutdown(&self) -> Result<(), MCHAError> {
+        let handle = self.handle.lock().unwrap().take();
+        if handle.is_none() {
+            return Err(MCHAError::NoServerRunning);
+        }
+
+        self.signal
+            .send(ChannelMessage::new(MessageData::CloseServer))?;
+
+        if let Some(h) = handle {
+            h.join().map_err(MCHAError::ThreadError)?;
+        }
+
+        self.storage.shutdown();
+
+        Ok(())
+    }
+}
+
+impl Drop for ServerThread {
     fn drop(&mut self) {
         let _ = self.shutdown();
     }
 }
 
+/// How the tokens of a namespace are handled, taken from its token generator
+#[derive(Clone)]
+pub(crate) struct Namespace {
+    /// [`TokenGenerator::normalize`] of the token generator
+    normalize: Arc<dyn Fn(&str) -> String + Send + Sync>,
+    /// Signed tokens are verified without storage, with the configured key
+    signer: Option<SignedToken>,
+}
+
+impl Namespace {
+    fn new<T: TokenGenerator>(token: &T, signer: Option<SignedToken>) -> Self {
+        let token = token.clone();
+
+        Self {
+            normalize: Arc::new(move |t: &str| token.normalize(t)),
+            signer,
+        }
+    }
+}
+
+/// The tokens issued through one [`VirtualHost`](crate::VirtualHost), see [`Server::vhost`]
+///
+/// Works just like the token methods on [`Server`], but only sees tokens of this virtual host.  
+#[derive(Clone)]
+pub struct VhostTokens<'a> {
+    storage: &'a TokenStorage,
+    vhost: Option<&'a str>,
+    namespace: &'a Namespace,
+}
+
+impl VhostTokens<'_> {
+    fn key(&self, token: &str) -> TokenKey {
+        (
+            self.vhost.map(str::to_string),
+            (self.namespace.normalize)(token),
+        )
+    }
+
+    /// See [`Server::verify`]
+    pub fn verify(&self, token: impl AsRef<str>, nonce: Option<&str>) -> Option<Player> {
+        self.storage.get(&self.key(token.as_ref()), nonce)
+    }
+
+    /// See [`Server::verify_signed`]
+    pub fn verify_signed(&self, token: impl AsRef<str>) -> Option<Player> {
+        let signer = self.namespace.signer.as_ref()?;
+        let mut player = signer.verify(token.as_ref()).ok()?.player;
+        player.vhost = self.vhost.map(str::to_string);
+
+        Some(player)
+    }
+
+    /// See [`Server::peek`]
+    pub fn peek(&self, token: impl AsRef<str>) -> Option<Player> {
+        self.storage.peek(&self.key(token.as_ref()))
+    }
+
+    /// See [`Server::claim`]
+    pub fn claim(&self, token: impl AsRef<str>, nonce: Option<&str>) -> Option<Claim> {
+        self.storage.claim(self.key(token.as_ref()), nonce)
+    }
+
+    /// See [`Server::revoke`]
+    pub fn revoke(&self, token: impl AsRef<str>) -> bool {
+        self.storage.revoke(&self.key(token.as_ref()))
+    }
+
+    /// See [`Server::token_for`]
+    pub fn token_for(&self, uuid: &Uuid) -> Option<String> {
+        self.storage.token_for(self.vhost, uuid)
+    }
+}
+
 impl Server {
     const CHANNEL_CAPACITY: usize = 1024;
 
@@ -44,30 +158,49 @@ impl Server {
     /// And then stores their `username` + `uuid` with a generated [`Token`](crate::TokenGenerator).  
     ///
     /// Using this token with [`Server::verify`], the player can be retreived later.  
-    pub fn start(config: ServerConfig) -> Self {
-        let storage = TokenStorage::new(config.token_ttl);
+    pub fn start<T: TokenGenerator, M: MessageGenerator, S: StatusProvider>(
+        mut config: ServerConfig<T, M, S>,
+    ) -> Self {
+        config.dedup_vhosts();
+
+        let mut namespaces = HashMap::from([(
+            None,
+            Namespace::new(&config.token, config.signed_token.clone()),
+        )]);
+        for vhost in &config.vhosts {
+            namespaces.insert(
+                Some(vhost.hostname.clone()),
+                Namespace::new(&vhost.token, vhost.signed_token.clone()),
+            );
+        }
+
         let broadcast = Broadcast::new();
+        let storage = TokenStorage::new(config.token_ttl, broadcast.clone());
+        let nonces = config
+            .nonce_binding
+            .clone()
+            .map(|b| NonceRegistry::new(b, config.token_ttl));
 
         let _broadcast = broadcast.clone();
+        let _storage = storage.clone();
+        let _nonces = nonces.clone();
 
         let (s_s, s_r) = bounded(Self::CHANNEL_CAPACITY);
         let s_t = thread::spawn(move || {
-            minecraft::server::start(config, _broadcast, s_r);
+            minecraft::server::start(config, _broadcast, _storage, _nonces, s_r);
         });
 
-        let server = Self {
+        Self {
             broadcast,
-            storage,
-            server_signal: s_s,
-            server_handle: Arc::new(Mutex::new(Some(s_t))),
-        };
-
-        let _storage = server.storage.clone();
-        server.on_join(move |user, token| {
-            _storage.insert(token.clone(), user.clone());
-        });
-
-        server
+            storage: storage.clone(),
+            nonces,
+            namespaces: Arc::new(namespaces),
+            thread: Arc::new(ServerThread {
+                signal: s_s,
+                handle: Mutex::new(Some(s_t)),
+                storage,
+            }),
+        }
     }
 
     /// Returns a associated [`Player`] if the given token is a valid one.  
@@ -75,16 +208,124 @@ impl Server {
     /// Note that a token can only be valid **once**.  
     ///
     /// If a valid token is used and returns a player, it is no longer valid after that.  
+    ///
+    /// The token is normalized with [`TokenGenerator::normalize`] first,  
+    /// so the default [`Token`](crate::Token) accepts e.g. `abc de` or the small caps `ᴀʙᴄᴅᴇ` for `ABCDE`.  
+    ///
+    /// If the token is bound to a nonce *(see [`NonceBinding`](crate::NonceBinding))*,  
+    /// the same nonce must be given, a token with a mismatched nonce stays valid.  
     /// ## Example
     /// ```no_run
+    /// # use mc_headless_auth::{Server, ServerConfig};
     /// let server = Server::start(ServerConfig::default());
     ///
     /// let token = String::from("MJMMJSLXHG");
-    /// let player = server.verify(&token);
+    /// let player = server.verify(&token, None);
     /// assert!(player.is_some());
     /// ```
-    pub fn verify(&self, token: impl AsRef<str>) -> Option<Player> {
-        self.storage.get(&token.as_ref().into())
+    pub fn verify(&self, token: impl AsRef<str>, nonce: Option<&str>) -> Option<Player> {
+        self.tokens().verify(token, nonce)
+    }
+
+    /// Verifies a token issued by a [`SignedToken`] generator, without looking at the token storage.  
+    ///
+    /// Returns `None` if [`ServerConfig::signed_token`](crate::ServerConfig::signed_token) isn't set,  
+    /// see [`ServerConfig::with_signed_token`](crate::ServerConfig::with_signed_token).  
+    /// Use [`SignedToken::verify`] directly to verify tokens in a process without a server.  
+    ///
+    /// Signed tokens can be verified multiple times until they expire,  
+    /// unless [`SignedToken::with_replay_protection`] is used.  
+    pub fn verify_signed(&self, token: impl AsRef<str>) -> Option<Player> {
+        self.tokens().verify_signed(token)
+    }
+
+    /// Returns the associated [`Player`] without consuming the token.  
+    ///
+    /// Unlike [`Server::verify`] the token stays valid afterwards.  
+    pub fn peek(&self, token: impl AsRef<str>) -> Option<Player> {
+        self.tokens().peek(token)
+    }
+
+    /// Claims a token for a two-phase verification.  
+    ///
+    /// The token is held by the returned [`Claim`] until it is either committed,  
+    /// which consumes it just like [`Server::verify`], or rolled back which makes it valid again.  
+    ///
+    /// Useful when something can fail after verifying, like writing the link to a database.  
+    /// ## Example
+    /// ```no_run
+    /// # use mc_headless_auth::{Server, ServerConfig};
+    /// # fn save_link(_: &mc_headless_auth::Player) -> Result<(), ()> { Ok(()) }
+    /// let server = Server::start(ServerConfig::default());
+    ///
+    /// if let Some(claim) = server.claim("MJMMJSLXHG", None) {
+    ///     match save_link(claim.player()) {
+    ///         Ok(_) => { claim.commit(); }
+    ///         Err(_) => claim.rollback(),
+    ///     }
+    /// }
+    /// ```
+    pub fn claim(&self, token: impl AsRef<str>, nonce: Option<&str>) -> Option<Claim> {
+        self.tokens().claim(token, nonce)
+    }
+
+    /// Invalidates a token, returns `true` if the token was valid.  
+    pub fn revoke(&self, token: impl AsRef<str>) -> bool {
+        self.tokens().revoke(token)
+    }
+
+    /// Returns the newest still valid token issued to a player.  
+    ///
+    /// Useful for support, to see which token a player was shown.  
+    pub fn token_for(&self, uuid: &Uuid) -> Option<String> {
+        self.tokens().token_for(uuid)
+    }
+
+    /// The tokens issued through a [`VirtualHost`](crate::VirtualHost), `None` if there is no such virtual host.  
+    ///
+    /// The hostname must be exactly as configured, including wildcards like `*.example.com`.  
+    /// Tokens of a virtual host can't be verified with the methods on [`Server`] itself.  
+    pub fn vhost(&self, hostname: &str) -> Option<VhostTokens<'_>> {
+        let (vhost, namespace) = self.namespaces.get_key_value(&Some(hostname.to_string()))?;
+
+        Some(VhostTokens {
+            storage: &self.storage,
+            vhost: vhost.as_deref(),
+            namespace,
+        })
+    }
+
+    /// The tokens issued outside of any virtual host
+    fn tokens(&self) -> VhostTokens<'_> {
+        VhostTokens {
+            storage: &self.storage,
+            vhost: None,
+            namespace: &self.namespaces[&None],
+        }
+    }
+
+    /// Registers a web session nonce and returns the hostname the player should join with.  
+    ///
+    /// Tokens issued to players joining through that hostname can only be verified with the same nonce.  
+    /// The nonce must be a valid hostname label *(`a-z`, `0-9` and `-`)* and stays registered for the token ttl.  
+    ///
+    /// Requires [`ServerConfig::nonce_binding`](crate::ServerConfig::nonce_binding) to be set.  
+    /// ## Example
+    /// ```no_run
+    /// # use mc_headless_auth::{NonceBinding, Server, ServerConfig};
+    /// let server = Server::start(ServerConfig {
+    ///     nonce_binding: Some(NonceBinding::new("auth.example.com")),
+    ///     ..Default::default()
+    /// });
+    ///
+    /// // "k3x9q.auth.example.com"
+    /// let address = server.register_nonce("k3x9q").unwrap();
+    /// ```
+    pub fn register_nonce(&self, nonce: impl AsRef<str>) -> Result<String, MCHAError> {
+        match &self.nonces {
+            Some(nonces) => nonces.register(nonce.as_ref()),
+            None => Err(MCHAError::NoNonceBinding),
+        }
     }
 
     /// A function to execute if a player connection in the server thread errors out.  
@@ -93,6 +334,7 @@ impl Server {
     ///
     /// ## Example
     /// ```no_run
+    /// # use mc_headless_auth::{Server, ServerConfig};
     /// let server = Server::start(ServerConfig::default());
     /// let _ = server.on_error(|e| {
     ///     eprintln!("{e:?}");
@@ -123,6 +365,7 @@ impl Server {
     ///
     /// ## Example
     /// ```no_run
+    /// # use mc_headless_auth::{Server, ServerConfig};
     /// let server = Server::start(ServerConfig::default());
     /// let _ = server.on_join(|player, token| {
     ///     println!("{} just joined and got {}", player.username, token);
@@ -149,30 +392,51 @@ impl Server {
         })
     }
 
+    /// A function to execute whenever a token expires without being verified.  
+    ///
+    /// ## Example
+    /// ```no_run
+    /// # use mc_headless_auth::{Server, ServerConfig};
+    /// let server = Server::start(ServerConfig::default());
+    /// let _ = server.on_expire(|player, token| {
+    ///     println!("{} never used {}", player.username, token);
+    /// });
+    /// ```
+    pub fn on_expire(
+        &self,
+        handler: impl Fn(&Player, &String) + Send + Sync + 'static,
+    ) -> JoinHandle<()> {
+        let b = self.broadcast.clone();
+        thread::spawn(move || {
+            let r = b.sub(Self::CHANNEL_CAPACITY);
+            while let Ok(msg) = r.recv() {
+                match &msg.data {
+                    MessageData::OnExpire { player, token } => {
+                        handler(player, token);
+                    }
+                    MessageData::CloseServer => {
+                        break;
+                    }
+                    _ => (),
+                }
+            }
+        })
+    }
+
     /// Sends a signal for the server to terminate itself and stop accepting connections.  
     ///
     /// This function also guarantees that the thread holding the server has returned fully.  
     ///
     /// ## Example
     /// ```no_run
+    /// # use std::{thread::sleep, time::Duration};
+    /// # use mc_headless_auth::{Server, ServerConfig};
     /// // Turn off the server after 5 seconds.  
     /// let server = Server::start(ServerConfig::default());
     /// sleep(Duration::from_secs(5));
     /// server.shutdown().unwrap();
     /// ```
     pub fn shutdown(&self) -> Result<(), MCHAError> {
-        let handle = self.server_handle.lock().unwrap().take();
-        if handle.is_none() {
-            return Err(MCHAError::NoServerRunning);
-        }
-
-        self.server_signal
-            .send(ChannelMessage::new(MessageData::CloseServer))?;
-
-        if let Some(h) = handle {
-            h.join().map_err(|e| MCHAError::ThreadError(e))?;
-        }
-
-        Ok(())
+        self.thread.shutdown()
     }
 }
@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use base64::prelude::*;
+use bytes::{Buf, BufMut, BytesMut};
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::{Player, error::SignedTokenError, token::TokenGenerator};
+
+type HmacSha256 = Hmac<Sha256>;
+/// Signatures of tokens that have been verified, mapped to when the token expires
+type SeenTokens = Arc<Mutex<HashMap<Vec<u8>, DateTime<Utc>>>>;
+
+/// A stateless token generator, the token itself carries the player and is authenticated with a HMAC
+///
+/// Any process that knows the key can verify the token with [`SignedToken::verify`],  
+/// no storage has to be shared with the process that issued it.  
+///
+/// Since the token is case sensitive and long, it should be copied rather than typed.  
+#[derive(Clone)]
+pub struct SignedToken {
+    key: Arc<[u8]>,
+    /// For how long a signed token is valid
+    ///
+    /// Defaults to `5 minutes`
+    pub ttl: Duration,
+    seen: Option<SeenTokens>,
+}
+
+/// The contents of a verified [`SignedToken`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedClaims {
+    pub player: Player,
+    pub issued: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
+
+impl SignedToken {
+    /// Version 2 widened the timestamp and ttl to 64 bits
+    const VERSION: u8 = 2;
+    /// HMAC-SHA256 truncated to 128 bits
+    const TAG_LEN: usize = 16;
+
+    pub fn new(key: impl AsRef<[u8]>) -> Self {
+        Self {
+            key: Arc::from(key.as_ref()),
+            ttl: Duration::from_secs(5 * 60),
+            seen: None,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Makes every token verifiable only once within this process
+    ///
+    /// Verified tokens are remembered until they expire, clones share the same memory.  
+    pub fn with_replay_protection(mut self) -> Self {
+        self.seen = Some(Arc::new(Mutex::new(HashMap::new())));
+        self
+    }
+
+    /// Verifies a token and returns what it was issued for
+    pub fn verify(&self, token: &str) -> Result<SignedClaims, SignedTokenError> {
+        let raw = BASE64_URL_SAFE_NO_PAD
+            .decode(token.trim())
+            .map_err(|_| SignedTokenError::Malformed)?;
+        if raw.len() <= Self::TAG_LEN {
+            return Err(SignedTokenError::Malformed);
+        }
+
+        let (payload, tag) = raw.split_at(raw.len() - Self::TAG_LEN);
+        self.mac(payload)
+            .verify_truncated_left(tag)
+            .map_err(|_| SignedTokenError::InvalidSignature)?;
+
+        let claims = Self::decode_payload(payload)?;
+        let now = Utc::now();
+        if claims.expires <= now {
+            return Err(SignedTokenError::Expired);
+        }
+
+        if let Some(seen) = &self.seen {
+            let mut seen = seen.lock().unwrap_or_else(|e| e.into_inner());
+            seen.retain(|_, expires| *expires > now);
+
+            if seen.insert(tag.to_vec(), claims.expires).is_some() {
+                return Err(SignedTokenError::Replayed);
+            }
+        }
+
+        Ok(claims)
+    }
+
+    fn mac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("hmac accepts any key length");
+        mac.update(payload);
+        mac
+    }
+
+    fn encode_payload(player: &Player, issued: DateTime<Utc>, ttl: Duration) -> BytesMut {
+        let mut data = BytesMut::new();
+
+        data.put_u8(Self::VERSION);
+        data.put_u128(player.uuid.as_u128());
+        data.put_i64(issued.timestamp());
+        data.put_u64(ttl.as_secs());
+        // usernames are at most 16 characters
+        data.put_u8(player.username.len() as u8);
+        data.extend_from_slice(player.username.as_bytes());
+
+        data
+    }
+
+    fn decode_payload(mut data: &[u8]) -> Result<SignedClaims, SignedTokenError> {
+        // version + uuid + issued + ttl + username length
+        if data.len() < 1 + 16 + 8 + 8 + 1 || data.get_u8() != Self::VERSION {
+            return Err(SignedTokenError::Malformed);
+        }
+
+        let uuid = Uuid::from_u128(data.get_u128());
+        let issued =
+            DateTime::from_timestamp(data.get_i64(), 0).ok_or(SignedTokenError::Malformed)?;
+        // a ttl too long for a date never expires
+        let expires = TimeDelta::try_seconds(data.get_u64().try_into().unwrap_or(i64::MAX))
+            .and_then(|ttl| issued.checked_add_signed(ttl))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+        let len = data.get_u8() as usize;
+        if data.len() != len {
+            return Err(SignedTokenError::Malformed);
+        }
+        let username = String::from_utf8(data.to_vec()).map_err(|_| SignedTokenError::Malformed)?;
+
+        Ok(SignedClaims {
+            player: Player {
+                uuid,
+                username,
+                vhost: None,
+                xuid: None,
+            },
+            issued,
+            expires,
+        })
+    }
+}
+
+impl TokenGenerator for SignedToken {
+    fn generate(&self, user: &Player) -> String {
+        let mut data = Self::encode_payload(user, Utc::now(), self.ttl);
+        let tag = self.mac(&data).finalize().into_bytes();
+        data.extend_from_slice(&tag[..Self::TAG_LEN]);
+
+        BASE64_URL_SAFE_NO_PAD.encode(&data)
+    }
+
+    /// Signed tokens are case sensitive and may contain dashes, only whitespace is stripped
+    fn normalize(&self, token: &str) -> String {
+        token.chars().filter(|c| !c.is_whitespace()).collect()
+    }
+
+    fn ttl(&self, _user: &Player, _token: &str) -> Option<Duration> {
+        Some(self.ttl)
+    }
+}
+
+impl Debug for SignedToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the key
+        f.debug_struct("SignedToken")
+            .field("ttl", &self.ttl)
+            .field("replay_protection", &self.seen.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use base64::prelude::*;
+    use uuid::uuid;
+
+    use crate::{Player, error::SignedTokenError, token::TokenGenerator};
+
+    use super::SignedToken;
+
+    fn player() -> Player {
+        Player {
+            uuid: uuid!("069a79f4-44e9-4726-a5be-fca90e38aaf5"),
+            username: "Notch".to_string(),
+            vhost: None,
+            xuid: None,
+        }
+    }
+
+    #[test]
+    fn generate_and_verify() {
+        let signer = SignedToken::new(b"key");
+        let token = signer.generate(&player());
+
+        let claims = signer.verify(&token).unwrap();
+        assert_eq!(claims.player, player());
+        assert_eq!(claims.expires - claims.issued, chrono::Duration::minutes(5));
+        // without replay protection a token can be verified again
+        assert!(signer.verify(&token).is_ok());
+    }
+
+    #[test]
+    fn tampered() {
+        let signer = SignedToken::new(b"key");
+        let token = signer.generate(&player());
+
+        let mut raw = BASE64_URL_SAFE_NO_PAD.decode(&token).unwrap();
+        let last = raw.len() - 1;
+        raw[last] ^= 1;
+        let tampered_tag = BASE64_URL_SAFE_NO_PAD.encode(&raw);
+        assert!(matches!(
+            signer.verify(&tampered_tag),
+            Err(SignedTokenError::InvalidSignature)
+        ));
+
+        // the username is the last part of the payload, before the tag
+        raw[last] ^= 1;
+        raw[last - 16] = b'X';
+        let tampered_payload = BASE64_URL_SAFE_NO_PAD.encode(&raw);
+        assert!(matches!(
+            signer.verify(&tampered_payload),
+            Err(SignedTokenError::InvalidSignature)
+        ));
+
+        assert!(matches!(
+            SignedToken::new(b"other key").verify(&token),
+            Err(SignedTokenError::InvalidSignature)
+        ));
+        assert!(matches!(
+            signer.verify("not a token"),
+            Err(SignedTokenError::Malformed)
+        ));
+    }
+
+    #[test]
+    fn expired() {
+        let signer = SignedToken::new(b"key").with_ttl(Duration::ZERO);
+        let token = signer.generate(&player());
+
+        assert!(matches!(
+            signer.verify(&token),
+            Err(SignedTokenError::Expired)
+        ));
+    }
+
+    #[test]
+    fn long_ttl() {
+        let signer = SignedToken::new(b"key").with_ttl(Duration::MAX);
+        let token = signer.generate(&player());
+
+        assert!(signer.verify(&token).is_ok());
+    }
+
+    #[test]
+    fn replayed() {
+        let signer = SignedToken::new(b"key").with_replay_protection();
+        let token = signer.generate(&player());
+
+        assert!(signer.verify(&token).is_ok());
+        // clones share what has been seen
+        assert!(matches!(
+            signer.clone().verify(&token),
+            Err(SignedTokenError::Replayed)
+        ));
+        let other = Player {
+            username: "jeb_".to_string(),
+            ..player()
+        };
+        assert!(signer.verify(&signer.generate(&other)).is_ok());
+    }
+}
@@ -0,0 +1,176 @@
+use std::{fmt::Debug, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    config::StatusConfig,
+    minecraft::{handshake::Handshake, intents::legacy_ping::_1dot6::PingHost},
+    player::Player,
+};
+
+/// Creates the status shown in the server list, called for every status request
+///
+/// [`StatusConfig`] is the default provider, which always returns itself.  
+///
+/// ## Example
+/// ```no_run
+/// # use mc_headless_auth::{Server, ServerConfig, StatusConfig, StatusProvider, StatusRequest, TextComponent};
+/// #[derive(Debug, Clone)]
+/// struct Maintenance;
+///
+/// impl StatusProvider for Maintenance {
+///     fn status(&self, _request: &StatusRequest) -> StatusConfig {
+///         StatusConfig {
+///             description: Some(TextComponent::text("Down for maintenance").color("red")),
+///             ..Default::default()
+///         }
+///     }
+/// }
+///
+/// let server = Server::start(ServerConfig::default().with_status(Maintenance));
+/// ```
+pub trait StatusProvider: Debug + Clone + Send + Sync + 'static {
+    fn status(&self, request: &StatusRequest) -> StatusConfig;
+
+    /// Whether the status only depends on the protocol version and virtual host
+    ///
+    /// Cacheable statuses are created once per protocol version range, and reused until the server stops.  
+    /// Defaults to `false`, so [`StatusProvider::status`] is called for every request.  
+    fn cacheable(&self) -> bool {
+        false
+    }
+}
+
+/// [`StatusProvider`] without `Clone`, so providers of different types can be mixed
+pub(crate) trait DynStatusProvider: Debug + Send + Sync + 'static {
+    fn status(&self, request: &StatusRequest) -> StatusConfig;
+    fn cacheable(&self) -> bool;
+}
+
+impl<S: StatusProvider> DynStatusProvider for S {
+    fn status(&self, request: &StatusRequest) -> StatusConfig {
+        StatusProvider::status(self, request)
+    }
+    fn cacheable(&self) -> bool {
+        StatusProvider::cacheable(self)
+    }
+}
+
+/// A status provider of any type, like the ones of [`VirtualHost`](crate::VirtualHost)s
+pub(crate) type AnyStatusProvider = Arc<dyn DynStatusProvider>;
+
+impl StatusProvider for AnyStatusProvider {
+    fn status(&self, request: &StatusRequest) -> StatusConfig {
+        DynStatusProvider::status(self.as_ref(), request)
+    }
+    fn cacheable(&self) -> bool {
+        DynStatusProvider::cacheable(self.as_ref())
+    }
+}
+
+/// What is known about the client requesting a status
+#[derive(Debug, Clone, Default)]
+pub struct StatusRequest {
+    /// The protocol version sent in the handshake, `None` for queries and pings before 1.6
+    pub protocol_version: Option<i32>,
+    /// The address the client used to connect, `None` for queries and pings before 1.6
+    pub server_address: Option<String>,
+    /// The port the client used to connect, `None` for queries and pings before 1.6
+    pub server_port: Option<u16>,
+    /// Whether this is a ping from a client before 1.7, which only shows the legacy description
+    pub legacy: bool,
+}
+
+/// Player counts and sample shown in the server list
+#[derive(Debug, Clone, Default)]
+pub struct StatusPlayers {
+    pub online: i32,
+    pub max: i32,
+    /// Players shown when hovering over the player count
+    pub sample: Vec<Player>,
+}
+
+/// The status a server answers a [status request](https://minecraft.wiki/w/Java_Edition_protocol/Server_List_Ping#Status_Response) with
+///
+/// Sent by this server, and parsed from other servers by [`ping`](crate::ping).  
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub version: StatusVersion,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub players: Option<StatusResponsePlayers>,
+    /// The description as a text component, in whatever format the server sent it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<Value>,
+    /// A `data:image/png;base64,` url
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub favicon: Option<String>,
+    #[serde(rename = "enforcesSecureChat", default)]
+    pub enforces_secure_chat: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusVersion {
+    pub name: String,
+    pub protocol: i32,
+    /// [`StatusConfig::protocol`], the advertised protocol is filled in per request
+    #[serde(skip)]
+    pub(crate) configured: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusResponsePlayers {
+    pub max: i32,
+    pub online: i32,
+    #[serde(default)]
+    pub sample: Vec<SamplePlayer>,
+}
+
+/// A player shown when hovering over the player count
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplePlayer {
+    pub name: String,
+    pub id: String,
+}
+
+impl StatusProvider for StatusConfig {
+    fn status(&self, _request: &StatusRequest) -> StatusConfig {
+        self.clone()
+    }
+
+    /// A config never changes while the server runs
+    fn cacheable(&self) -> bool {
+        true
+    }
+}
+
+impl StatusRequest {
+    pub(crate) fn legacy() -> Self {
+        Self {
+            legacy: true,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<&Handshake> for StatusRequest {
+    fn from(handshake: &Handshake) -> Self {
+        Self {
+            protocol_version: Some(handshake.protocol_version.0),
+            server_address: Some(handshake.server_address.0.clone()),
+            server_port: Some(handshake.server_port),
+            legacy: false,
+        }
+    }
+}
+
+impl From<&PingHost> for StatusRequest {
+    fn from(ping: &PingHost) -> Self {
+        Self {
+            protocol_version: Some(ping.protocol_version),
+            server_address: Some(ping.hostname.clone()),
+            server_port: Some(ping.port),
+            legacy: true,
+        }
+    }
+}
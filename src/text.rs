@@ -0,0 +1,548 @@
+use serde_json::{Map, Value, json};
+use simdnbt::owned::{NbtCompound, NbtList, NbtTag};
+
+use crate::minecraft::protocol_version;
+
+/// A [text component](https://minecraft.wiki/w/Text_component_format) that can be sent to any client
+///
+/// Serializes to NBT for the configuration disconnect *(1.20.3+)*,  
+/// and to JSON for login disconnects and the status description.  
+///
+/// ## Example
+/// ```
+/// # use mc_headless_auth::TextComponent;
+/// let msg = TextComponent::text("Token: ")
+///     .extra(TextComponent::text("ABCDEF").color("#36bf5a").bold(true))
+///     .extra(TextComponent::text("\nUse this to link your account").italic(true));
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextComponent {
+    pub content: Content,
+    /// A named color like `red` or a hex color like `#36bf5a`
+    pub color: Option<String>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+    pub underlined: Option<bool>,
+    pub strikethrough: Option<bool>,
+    pub obfuscated: Option<bool>,
+    pub click_event: Option<ClickEvent>,
+    pub hover_event: Option<HoverEvent>,
+    /// Components appended after this one, inheriting its style
+    pub extra: Vec<TextComponent>,
+}
+
+/// What a [`TextComponent`] displays
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Text(String),
+    /// A translation key resolved by the client, with arguments
+    Translate {
+        key: String,
+        with: Vec<TextComponent>,
+    },
+}
+
+/// What happens when a [`TextComponent`] is clicked
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClickEvent {
+    OpenUrl(String),
+    CopyToClipboard(String),
+    SuggestCommand(String),
+    RunCommand(String),
+}
+
+/// What is shown when hovering over a [`TextComponent`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum HoverEvent {
+    ShowText(Box<TextComponent>),
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Content::Text(String::new())
+    }
+}
+
+impl TextComponent {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            content: Content::Text(text.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn translate(key: impl Into<String>) -> Self {
+        Self {
+            content: Content::Translate {
+                key: key.into(),
+                with: Vec::new(),
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Adds an argument to a translated component, ignored for text components
+    pub fn with(mut self, arg: impl Into<TextComponent>) -> Self {
+        if let Content::Translate { with, .. } = &mut self.content {
+            with.push(arg.into());
+        }
+        self
+    }
+
+    pub fn color(mut self, color: impl Into<String>) -> Self {
+        self.color = Some(color.into());
+        self
+    }
+
+    pub fn bold(mut self, bold: bool) -> Self {
+        self.bold = Some(bold);
+        self
+    }
+
+    pub fn italic(mut self, italic: bool) -> Self {
+        self.italic = Some(italic);
+        self
+    }
+
+    pub fn underlined(mut self, underlined: bool) -> Self {
+        self.underlined = Some(underlined);
+        self
+    }
+
+    pub fn strikethrough(mut self, strikethrough: bool) -> Self {
+        self.strikethrough = Some(strikethrough);
+        self
+    }
+
+    pub fn obfuscated(mut self, obfuscated: bool) -> Self {
+        self.obfuscated = Some(obfuscated);
+        self
+    }
+
+    pub fn click(mut self, event: ClickEvent) -> Self {
+        self.click_event = Some(event);
+        self
+    }
+
+    /// Copies `value` to the clipboard when clicked
+    pub fn copy_on_click(self, value: impl Into<String>) -> Self {
+        self.click(ClickEvent::CopyToClipboard(value.into()))
+    }
+
+    pub fn hover(mut self, event: HoverEvent) -> Self {
+        self.hover_event = Some(event);
+        self
+    }
+
+    /// Shows `text` as a tooltip when hovered
+    pub fn hover_text(self, text: impl Into<TextComponent>) -> Self {
+        self.hover(HoverEvent::ShowText(Box::new(text.into())))
+    }
+
+    pub fn extra(mut self, component: impl Into<TextComponent>) -> Self {
+        self.extra.push(component.into());
+        self
+    }
+
+    /// Serializes into JSON, field names depend on the protocol version
+    ///
+    /// 1.21.5 renamed `clickEvent`/`hoverEvent` to `click_event`/`hover_event`,  
+    /// along with the fields inside them.  
+    pub fn to_json(&self, protocol_version: i32) -> Value {
+        let snake_case = protocol_version >= protocol_version::V1_21_5;
+        let mut obj = Map::new();
+
+        match &self.content {
+            Content::Text(text) => {
+                obj.insert("text".into(), json!(text));
+            }
+            Content::Translate { key, with } => {
+                obj.insert("translate".into(), json!(key));
+                if !with.is_empty() {
+                    let with = with.iter().map(|c| c.to_json(protocol_version));
+                    obj.insert("with".into(), Value::Array(with.collect()));
+                }
+            }
+        }
+
+        if let Some(color) = &self.color {
+            obj.insert("color".into(), json!(color));
+        }
+
+        for (key, value) in [
+            ("bold", self.bold),
+            ("italic", self.italic),
+            ("underlined", self.underlined),
+            ("strikethrough", self.strikethrough),
+            ("obfuscated", self.obfuscated),
+        ] {
+            if let Some(value) = value {
+                obj.insert(key.into(), json!(value));
+            }
+        }
+
+        if let Some(event) = &self.click_event {
+            let (action, field, value) = match event {
+                ClickEvent::OpenUrl(url) => ("open_url", "url", url),
+                ClickEvent::CopyToClipboard(value) => ("copy_to_clipboard", "value", value),
+                ClickEvent::SuggestCommand(cmd) => ("suggest_command", "command", cmd),
+                ClickEvent::RunCommand(cmd) => ("run_command", "command", cmd),
+            };
+            let (key, field) = match snake_case {
+                true => ("click_event", field),
+                false => ("clickEvent", "value"),
+            };
+
+            obj.insert(key.into(), json!({ "action": action, field: value }));
+        }
+
+        if let Some(HoverEvent::ShowText(text)) = &self.hover_event {
+            let (key, field) = match snake_case {
+                true => ("hover_event", "value"),
+                false => ("hoverEvent", "contents"),
+            };
+
+            obj.insert(
+                key.into(),
+                json!({ "action": "show_text", field: text.to_json(protocol_version) }),
+            );
+        }
+
+        if !self.extra.is_empty() {
+            let extra = self.extra.iter().map(|c| c.to_json(protocol_version));
+            obj.insert("extra".into(), Value::Array(extra.collect()));
+        }
+
+        Value::Object(obj)
+    }
+
+    /// Serializes into NBT, used by clients on 1.20.3+
+    pub fn to_nbt(&self, protocol_version: i32) -> NbtTag {
+        json_to_nbt(&self.to_json(protocol_version))
+    }
+
+    /// Parses a string with `§` formatting codes, like `§aGreen §lbold`
+    ///
+    /// Also understands hex colors in the `§x§r§r§g§g§b§b` format.  
+    pub fn from_legacy(text: &str) -> Self {
+        let mut root = TextComponent::text("");
+        let mut style = TextComponent::default();
+        let mut buffer = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let Some(code) = chars
+                .peek()
+                .filter(|_| c == '§')
+                .map(|c| c.to_ascii_lowercase())
+            else {
+                buffer.push(c);
+                continue;
+            };
+            chars.next();
+
+            if !buffer.is_empty() {
+                root.extra.push(TextComponent {
+                    content: Content::Text(std::mem::take(&mut buffer)),
+                    ..style.clone()
+                });
+            }
+
+            if let Some(color) = legacy_color(code) {
+                // colors reset the formatting
+                style = TextComponent::default().color(color);
+            } else if code == 'x' {
+                // only a complete sequence is a hex color, otherwise its codes are read on their own
+                let mut ahead = chars.clone();
+                let hex: Option<String> = (0..6)
+                    .map(|_| match (ahead.next(), ahead.next()) {
+                        (Some('§'), Some(digit)) if digit.is_ascii_hexdigit() => Some(digit),
+                        _ => None,
+                    })
+                    .collect();
+                if let Some(hex) = hex {
+                    chars = ahead;
+                    style = TextComponent::default().color(format!("#{}", hex.to_lowercase()));
+                }
+            } else {
+                match code {
+                    'k' => style.obfuscated = Some(true),
+                    'l' => style.bold = Some(true),
+                    'm' => style.strikethrough = Some(true),
+                    'n' => style.underlined = Some(true),
+                    'o' => style.italic = Some(true),
+                    'r' => style = TextComponent::default(),
+                    _ => {}
+                }
+            }
+        }
+
+        if !buffer.is_empty() {
+            root.extra.push(TextComponent {
+                content: Content::Text(buffer),
+                ..style
+            });
+        }
+
+        match root.extra.len() {
+            0 => root,
+            // a single unstyled segment doesn't need the wrapper
+            1 if root.extra[0] == TextComponent::text(text) => root.extra.remove(0),
+            _ => root,
+        }
+    }
+
+    /// Renders into a string with `§` formatting codes, for clients before 1.7
+    ///
+    /// Hex colors are replaced with the closest named color,  
+    /// events are dropped and translations are rendered as their key.  
+    pub fn to_legacy(&self) -> String {
+        let mut out = String::new();
+        self.write_legacy(&TextComponent::default(), &mut out);
+        out
+    }
+
+    /// Only the text of this component and its children, without any formatting
+    pub fn to_plain(&self) -> String {
+        let mut out = String::new();
+        self.write_plain(&mut out);
+        out
+    }
+
+    fn write_legacy(&self, parent: &TextComponent, out: &mut String) {
+        // children inherit the style of their parent
+        let style = TextComponent {
+            color: self.color.clone().or_else(|| parent.color.clone()),
+            bold: self.bold.or(parent.bold),
+            italic: self.italic.or(parent.italic),
+            underlined: self.underlined.or(parent.underlined),
+            strikethrough: self.strikethrough.or(parent.strikethrough),
+            obfuscated: self.obfuscated.or(parent.obfuscated),
+            ..Default::default()
+        };
+
+        let text = match &self.content {
+            Content::Text(text) => text.as_str(),
+            Content::Translate { key, .. } => key.as_str(),
+        };
+        if !text.is_empty() {
+            // every segment starts from a clean style, colors reset the formatting
+            match style.color.as_deref().and_then(nearest_legacy_code) {
+                Some(code) => out.extend(['§', code]),
+                None => out.push_str("§r"),
+            }
+            for (code, enabled) in [
+                ('k', style.obfuscated),
+                ('l', style.bold),
+                ('m', style.strikethrough),
+                ('n', style.underlined),
+                ('o', style.italic),
+            ] {
+                if enabled == Some(true) {
+                    out.extend(['§', code]);
+                }
+            }
+            out.push_str(text);
+        }
+
+        if let Content::Translate { with, .. } = &self.content {
+            for arg in with {
+                arg.write_legacy(&style, out);
+            }
+        }
+        for child in &self.extra {
+            child.write_legacy(&style, out);
+        }
+    }
+
+    fn write_plain(&self, out: &mut String) {
+        match &self.content {
+            Content::Text(text) => out.push_str(text),
+            Content::Translate { key, with } => {
+                out.push_str(key);
+                with.iter().for_each(|arg| arg.write_plain(out));
+            }
+        }
+        self.extra.iter().for_each(|child| child.write_plain(out));
+    }
+}
+
+/// Legacy color codes with their name and rgb value
+const LEGACY_COLORS: [(char, &str, u32); 16] = [
+    ('0', "black", 0x000000),
+    ('1', "dark_blue", 0x0000AA),
+    ('2', "dark_green", 0x00AA00),
+    ('3', "dark_aqua", 0x00AAAA),
+    ('4', "dark_red", 0xAA0000),
+    ('5', "dark_purple", 0xAA00AA),
+    ('6', "gold", 0xFFAA00),
+    ('7', "gray", 0xAAAAAA),
+    ('8', "dark_gray", 0x555555),
+    ('9', "blue", 0x5555FF),
+    ('a', "green", 0x55FF55),
+    ('b', "aqua", 0x55FFFF),
+    ('c', "red", 0xFF5555),
+    ('d', "light_purple", 0xFF55FF),
+    ('e', "yellow", 0xFFFF55),
+    ('f', "white", 0xFFFFFF),
+];
+
+fn legacy_color(code: char) -> Option<&'static str> {
+    LEGACY_COLORS
+        .iter()
+        .find(|(c, ..)| *c == code)
+        .map(|(_, name, _)| *name)
+}
+
+/// Finds the legacy code of a named color, or the closest one to a hex color
+fn nearest_legacy_code(color: &str) -> Option<char> {
+    if let Some((code, ..)) = LEGACY_COLORS.iter().find(|(_, name, _)| *name == color) {
+        return Some(*code);
+    }
+
+    let rgb = u32::from_str_radix(color.strip_prefix('#')?, 16).ok()?;
+    let channels = |c: u32| [(c >> 16) & 0xFF, (c >> 8) & 0xFF, c & 0xFF].map(|v| v as i32);
+    let distance = |c: u32| {
+        channels(c)
+            .iter()
+            .zip(channels(rgb))
+            .map(|(a, b)| (a - b).pow(2))
+            .sum::<i32>()
+    };
+
+    LEGACY_COLORS
+        .iter()
+        .min_by_key(|(_, _, c)| distance(*c))
+        .map(|(code, ..)| *code)
+}
+
+impl From<&str> for TextComponent {
+    fn from(value: &str) -> Self {
+        TextComponent::text(value)
+    }
+}
+
+impl From<String> for TextComponent {
+    fn from(value: String) -> Self {
+        TextComponent::text(value)
+    }
+}
+
+/// Converts the JSON of a text component into the equivalent NBT
+fn json_to_nbt(value: &Value) -> NbtTag {
+    match value {
+        Value::Object(obj) => {
+            let mut compound = NbtCompound::new();
+            for (key, value) in obj {
+                compound.insert(key.as_str(), json_to_nbt(value));
+            }
+            NbtTag::Compound(compound)
+        }
+        // components only ever contain lists of components
+        Value::Array(list) => NbtTag::List(NbtList::Compound(
+            list.iter()
+                .filter_map(|v| match json_to_nbt(v) {
+                    NbtTag::Compound(c) => Some(c),
+                    _ => None,
+                })
+                .collect(),
+        )),
+        Value::String(str) => NbtTag::String(str.as_str().into()),
+        Value::Bool(bool) => NbtTag::Byte(*bool as i8),
+        Value::Number(num) => NbtTag::Int(num.as_i64().unwrap_or_default() as i32),
+        Value::Null => NbtTag::String("".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(text: &str) -> TextComponent {
+        TextComponent::text(text)
+    }
+
+    #[test]
+    fn from_legacy_plain() {
+        assert_eq!(TextComponent::from_legacy("Hello"), segment("Hello"));
+        assert_eq!(TextComponent::from_legacy(""), segment(""));
+        // a trailing `§` has no code, so it is kept as text
+        assert_eq!(TextComponent::from_legacy("Hello§"), segment("Hello§"));
+    }
+
+    #[test]
+    fn from_legacy_codes() {
+        let text = TextComponent::from_legacy("§aGreen §lbold§CRed");
+
+        assert_eq!(
+            text.extra,
+            [
+                segment("Green ").color("green"),
+                segment("bold").color("green").bold(true),
+                // colors reset the formatting
+                segment("Red").color("red"),
+            ]
+        );
+    }
+
+    #[test]
+    fn from_legacy_hex() {
+        let text = TextComponent::from_legacy("§x§3§6§B§f§5§aHex");
+
+        assert_eq!(text.extra, [segment("Hex").color("#36bf5a")]);
+    }
+
+    #[test]
+    fn from_legacy_malformed_hex() {
+        // too short, the codes after `§x` are read on their own
+        assert_eq!(
+            TextComponent::from_legacy("§x§1§2Text").extra,
+            [segment("Text").color("dark_green")]
+        );
+        // not a hex digit, the last color before the text wins
+        assert_eq!(
+            TextComponent::from_legacy("§x§1§2§3§z§5§6Text").extra,
+            [segment("Text").color("gold")]
+        );
+        // missing `§` between the digits
+        assert_eq!(
+            TextComponent::from_legacy("§x§1§2§3456Text").to_plain(),
+            "456Text"
+        );
+        assert_eq!(TextComponent::from_legacy("§x").to_plain(), "");
+    }
+
+    #[test]
+    fn to_legacy() {
+        let text = segment("Token: ")
+            .extra(segment("ABCD").color("#36bf5a").bold(true))
+            .extra(segment(" done"));
+
+        assert_eq!(text.to_legacy(), "§rToken: §a§lABCD§r done");
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        let legacy = "§aGreen §lbold§r plain §x§f§f§5§5§5§5red";
+        let text = TextComponent::from_legacy(legacy);
+
+        assert_eq!(text.to_legacy(), "§aGreen §a§lbold§r plain §cred");
+        assert_eq!(
+            TextComponent::from_legacy(&text.to_legacy()).to_plain(),
+            text.to_plain()
+        );
+    }
+
+    #[test]
+    fn to_plain() {
+        let text = TextComponent::translate("multiplayer.disconnect.")
+            .with(segment("kicked").color("red"))
+            .extra(segment(" again").bold(true));
+
+        assert_eq!(text.to_plain(), "multiplayer.disconnect.kicked again");
+        assert_eq!(
+            TextComponent::from_legacy("§aGreen §lbold").to_plain(),
+            "Green bold"
+        );
+    }
+}
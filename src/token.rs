@@ -1,15 +1,76 @@
-use std::fmt::Debug;
+use std::{
+    fmt::Debug,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
 
 use rand::RngExt;
 
-use crate::Player;
+use crate::{MCHAError, Player};
 
 /// Generates a token based off a user, and optionally a different way to display it  
-pub trait TokenGenerator: Debug + Clone {
+pub trait TokenGenerator: Debug + Clone + Send + Sync + 'static {
     fn generate(&self, user: &Player) -> String;
     fn display(&self, token: &str) -> String {
         token.to_string()
     }
+    /// Turns user input into the form the token is stored as, applied when verifying
+    ///
+    /// The default maps small caps back to ASCII, strips whitespace and dashes and uppercases.  
+    /// A generated token is stored as `normalize(generate(..))`, so both must agree.  
+    fn normalize(&self, token: &str) -> String {
+        token
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '-')
+            .map(Token::from_small_caps)
+            .flat_map(char::to_uppercase)
+            .collect()
+    }
+    /// Overrides [`ServerConfig::token_ttl`](crate::ServerConfig::token_ttl) for a generated token  
+    fn ttl(&self, _user: &Player, _token: &str) -> Option<Duration> {
+        None
+    }
+}
+
+/// [`TokenGenerator`] without `Clone`, so generators of different types can be mixed
+pub(crate) trait DynTokenGenerator: Debug + Send + Sync + 'static {
+    fn generate(&self, user: &Player) -> String;
+    fn display(&self, token: &str) -> String;
+    fn normalize(&self, token: &str) -> String;
+    fn ttl(&self, user: &Player, token: &str) -> Option<Duration>;
+}
+
+impl<T: TokenGenerator> DynTokenGenerator for T {
+    fn generate(&self, user: &Player) -> String {
+        TokenGenerator::generate(self, user)
+    }
+    fn display(&self, token: &str) -> String {
+        TokenGenerator::display(self, token)
+    }
+    fn normalize(&self, token: &str) -> String {
+        TokenGenerator::normalize(self, token)
+    }
+    fn ttl(&self, user: &Player, token: &str) -> Option<Duration> {
+        TokenGenerator::ttl(self, user, token)
+    }
+}
+
+/// A token generator of any type, like the ones of [`VirtualHost`](crate::VirtualHost)s
+pub(crate) type AnyTokenGenerator = Arc<dyn DynTokenGenerator>;
+
+impl TokenGenerator for AnyTokenGenerator {
+    fn generate(&self, user: &Player) -> String {
+        DynTokenGenerator::generate(self.as_ref(), user)
+    }
+    fn display(&self, token: &str) -> String {
+        DynTokenGenerator::display(self.as_ref(), token)
+    }
+    fn normalize(&self, token: &str) -> String {
+        DynTokenGenerator::normalize(self.as_ref(), token)
+    }
+    fn ttl(&self, user: &Player, token: &str) -> Option<Duration> {
+        DynTokenGenerator::ttl(self.as_ref(), user, token)
+    }
 }
 
 /// Default token generator, a string of 10 random A-Z character
@@ -25,6 +86,14 @@ impl Token {
         'S', 'T', 'U', 'V', 'W', 'X', 'Y', 'Z',
     ];
     const LENGTH: usize = 10;
+
+    /// Maps a small caps glyph from [`Token::display`] back to its ASCII letter
+    pub(crate) fn from_small_caps(c: char) -> char {
+        match Self::FANCY_CHARS.iter().position(|f| f == &c) {
+            Some(idx) => Self::CHARS[idx],
+            None => c,
+        }
+    }
 }
 
 impl TokenGenerator for Token {
@@ -44,88 +113,846 @@ impl TokenGenerator for Token {
     fn display(&self, token: &str) -> String {
         let mut out = String::with_capacity(token.len());
         for char in token.chars() {
-            let idx = Self::CHARS.iter().position(|c| c == &char).unwrap();
-            out.push(Self::FANCY_CHARS[idx]);
+            // anything without a small caps variant is displayed as is
+            match Self::CHARS.iter().position(|c| c == &char) {
+                Some(idx) => out.push(Self::FANCY_CHARS[idx]),
+                None => out.push(char),
+            }
         }
         return out;
     }
 }
 
+/// Token generator using a custom alphabet and length
+///
+/// The displayed token can optionally be split into groups, like `ABCD-EFGH`.  
+#[derive(Debug, Clone)]
+pub struct AlphabetToken {
+    /// The characters a token is made of, never empty
+    alphabet: Vec<char>,
+    /// How many characters a token has, never `0`
+    length: usize,
+    /// Splits the displayed token into groups of this many characters
+    pub group: Option<usize>,
+    /// The character put between groups
+    pub separator: char,
+}
+
+impl AlphabetToken {
+    /// Uppercase letters and digits without the easily confused `0`, `O`, `1` and `I`
+    pub const UNAMBIGUOUS: &str = "ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+    /// Errors if the alphabet is empty or the length is `0`, as every token would be empty
+    pub fn new(alphabet: impl AsRef<str>, length: usize) -> Result<Self, MCHAError> {
+        let alphabet: Vec<char> = alphabet.as_ref().chars().collect();
+        if alphabet.is_empty() {
+            return Err(MCHAError::EmptyToken("alphabet is empty"));
+        }
+        if length == 0 {
+            return Err(MCHAError::EmptyToken("length is 0"));
+        }
+
+        Ok(Self {
+            alphabet,
+            length,
+            group: None,
+            separator: '-',
+        })
+    }
+
+    /// A token made from [`AlphabetToken::UNAMBIGUOUS`], errors if the length is `0`
+    pub fn unambiguous(length: usize) -> Result<Self, MCHAError> {
+        Self::new(Self::UNAMBIGUOUS, length)
+    }
+
+    /// The characters a token is made of
+    pub fn alphabet(&self) -> &[char] {
+        &self.alphabet
+    }
+
+    /// How many characters a token has
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    /// Displays the token in groups of `size` characters
+    pub fn grouped(mut self, size: usize) -> Self {
+        self.group = Some(size);
+        self
+    }
+}
+
+impl Default for AlphabetToken {
+    /// Eight unambiguous characters displayed as `ABCD-EFGH`
+    fn default() -> Self {
+        Self::unambiguous(8)
+            .expect("the unambiguous alphabet isn't empty")
+            .grouped(4)
+    }
+}
+
+impl TokenGenerator for AlphabetToken {
+    fn generate(&self, _: &Player) -> String {
+        let mut rng = rand::rng();
+
+        (0..self.length)
+            .map(|_| self.alphabet[rng.random_range(0..self.alphabet.len())])
+            .collect()
+    }
+
+    fn display(&self, token: &str) -> String {
+        let size = match self.group {
+            Some(size) if size > 0 => size,
+            _ => return token.to_string(),
+        };
+
+        let chars: Vec<char> = token.chars().collect();
+        chars
+            .chunks(size)
+            .map(|c| c.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join(&self.separator.to_string())
+    }
+
+    fn normalize(&self, token: &str) -> String {
+        let stripped = token
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != self.separator)
+            .map(Token::from_small_caps);
+
+        // only fold the case if the alphabet itself can't tell cases apart
+        if self.alphabet.iter().any(|c| c.is_lowercase()) {
+            stripped.collect()
+        } else {
+            stripped.flat_map(char::to_uppercase).collect()
+        }
+    }
+}
+
+/// Token generator for a numeric pin, `6` digits by default
+#[derive(Debug, Clone)]
+pub struct NumericToken {
+    /// How many digits a token has, never `0`
+    digits: usize,
+}
+
+impl NumericToken {
+    /// Errors if `digits` is `0`, as every token would be empty
+    pub fn new(digits: usize) -> Result<Self, MCHAError> {
+        if digits == 0 {
+            return Err(MCHAError::EmptyToken("digits is 0"));
+        }
+
+        Ok(Self { digits })
+    }
+
+    /// How many digits a token has
+    pub fn digits(&self) -> usize {
+        self.digits
+    }
+}
+
+impl Default for NumericToken {
+    fn default() -> Self {
+        Self { digits: 6 }
+    }
+}
+
+impl TokenGenerator for NumericToken {
+    fn generate(&self, _: &Player) -> String {
+        let mut rng = rand::rng();
+
+        (0..self.digits)
+            .map(|_| char::from(b'0' + rng.random_range(0..10u8)))
+            .collect()
+    }
+}
+
+/// Token generator made of random words, like `maple-otter-rocket`
+///
+/// Words are picked from an embedded list of ~480 short english words,  
+/// so three words give about 110 million combinations.  
+#[derive(Debug, Clone)]
+pub struct WordToken {
+    /// How many words a token has, never `0`
+    words: usize,
+    /// The character put between words
+    pub separator: char,
+}
+
+impl WordToken {
+    const WORDLIST: &str = include_str!("words.txt");
+
+    /// Errors if `words` is `0`, as every token would be empty
+    pub fn new(words: usize) -> Result<Self, MCHAError> {
+        if words == 0 {
+            return Err(MCHAError::EmptyToken("words is 0"));
+        }
+
+        Ok(Self {
+            words,
+            ..Default::default()
+        })
+    }
+
+    /// How many words a token has
+    pub fn words(&self) -> usize {
+        self.words
+    }
+}
+
+/// [`WordToken::WORDLIST`] split into words, once
+static WORDS: LazyLock<Vec<&'static str>> = LazyLock::new(|| WordToken::WORDLIST.lines().collect());
+
+impl Default for WordToken {
+    fn default() -> Self {
+        Self {
+            words: 3,
+            separator: '-',
+        }
+    }
+}
+
+impl TokenGenerator for WordToken {
+    fn generate(&self, _: &Player) -> String {
+        let mut rng = rand::rng();
+
+        (0..self.words)
+            .map(|_| WORDS[rng.random_range(0..WORDS.len())])
+            .collect::<Vec<_>>()
+            .join(&self.separator.to_string())
+    }
+}
+
 pub mod storage {
     use std::{
-        collections::HashMap,
-        sync::{Arc, Mutex},
-        thread,
+        cmp::Reverse,
+        collections::{BinaryHeap, HashMap},
+        sync::{Arc, Condvar, Mutex, MutexGuard},
+        thread::{self, JoinHandle},
         time::Duration,
     };
 
-    use chrono::{DateTime, Utc};
+    use chrono::{DateTime, TimeDelta, Utc};
+    use uuid::Uuid;
 
-    use crate::Player;
+    use crate::{
+        Player,
+        broadcast::Broadcast,
+        channel_message::{ChannelMessage, MessageData},
+    };
 
     #[derive(Debug, Clone)]
     pub(crate) struct StorageCell {
         pub time: DateTime<Utc>,
+        pub expires: DateTime<Utc>,
         pub data: Player,
+        /// The token as it was generated and shown to the player
+        pub shown: String,
+        /// The web session nonce this token is bound to, if any
+        pub nonce: Option<String>,
+    }
+
+    impl StorageCell {
+        /// Tokens without a nonce can be used with any nonce,  
+        /// bound tokens require the exact nonce they were issued with
+        fn matches_nonce(&self, nonce: Option<&str>) -> bool {
+            match &self.nonce {
+                Some(bound) => nonce.is_some_and(|n| n.eq_ignore_ascii_case(bound)),
+                None => true,
+            }
+        }
+    }
+
+    /// A normalized token within the namespace of a virtual host, `None` is the default namespace
+    pub(crate) type TokenKey = (Option<String>, String);
+
+    #[derive(Debug)]
+    pub(crate) struct StorageInner {
+        /// A map of tokens that are mapped to a user and a date when the token was set
+        tokens: HashMap<TokenKey, StorageCell>,
+        /// Min-heap of when tokens expire, entries for already removed tokens are skipped
+        expiries: BinaryHeap<Reverse<(DateTime<Utc>, TokenKey)>>,
+        /// Tokens taken out by a [`Claim`] that isn't committed or rolled back yet
+        held: HashMap<TokenKey, Held>,
+        next_claim: u64,
+        running: bool,
+    }
+
+    /// A claimed token, see [`StorageInner::held`]
+    #[derive(Debug)]
+    struct Held {
+        /// Tells claims of the same token apart, if it was issued again in the meantime
+        claim: u64,
+        uuid: Uuid,
+        /// Revoked or replaced while claimed, so a rollback must not bring it back
+        revoked: bool,
     }
 
-    pub(crate) type StorageInternal = Arc<Mutex<HashMap<String, StorageCell>>>;
+    impl StorageInner {
+        fn push(&mut self, token: TokenKey, cell: StorageCell) {
+            self.expiries.push(Reverse((cell.expires, token.clone())));
+            self.tokens.insert(token, cell);
+        }
+
+        /// Gets a token that hasn't expired, expired tokens are removed and returned in `expired`
+        fn get_valid(
+            &mut self,
+            token: &TokenKey,
+            expired: &mut Option<StorageCell>,
+        ) -> Option<&StorageCell> {
+            if self.tokens.get(token)?.expires <= Utc::now() {
+                *expired = self.tokens.remove(token);
+                return None;
+            }
+
+            self.tokens.get(token)
+        }
+    }
+
+    pub(crate) type StorageInternal = Arc<(Mutex<StorageInner>, Condvar)>;
 
     #[derive(Debug, Clone)]
     pub struct TokenStorage {
-        /// A map of tokens that are mapped to a user and a date when the token was set
         tokens: StorageInternal,
+        ttl: Duration,
+        broadcast: Broadcast,
+        cleaner: Arc<Mutex<Option<JoinHandle<()>>>>,
     }
 
     impl TokenStorage {
-        pub fn new(ttl: Duration) -> Self {
+        /// How long the cleaner waits when there are no tokens at all
+        const IDLE_INTERVAL: Duration = Duration::from_secs(60);
+
+        pub fn new(ttl: Duration, broadcast: Broadcast) -> Self {
             let storage = Self {
-                tokens: Arc::new(Mutex::new(HashMap::new())),
+                tokens: Arc::new((
+                    Mutex::new(StorageInner {
+                        tokens: HashMap::new(),
+                        expiries: BinaryHeap::new(),
+                        held: HashMap::new(),
+                        next_claim: 0,
+                        running: true,
+                    }),
+                    Condvar::new(),
+                )),
+                ttl,
+                broadcast,
+                cleaner: Arc::new(Mutex::new(None)),
             };
 
             let _tokens = storage.tokens.clone();
-            thread::spawn(move || Self::start_storage_cleaner(_tokens, ttl));
+            let _broadcast = storage.broadcast.clone();
+            let handle = thread::spawn(move || Self::start_storage_cleaner(_tokens, _broadcast));
+            *storage.cleaner.lock().unwrap() = Some(handle);
 
             storage
         }
 
-        pub fn insert(&self, token: String, user: Player) -> i64 {
-            let mut lock = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        fn lock(&self) -> MutexGuard<'_, StorageInner> {
+            self.tokens.0.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        /// Inserts a normalized token that expires after `ttl`, or the storage default ttl
+        pub fn insert(
+            &self,
+            token: TokenKey,
+            shown: String,
+            user: Player,
+            nonce: Option<String>,
+            ttl: Option<Duration>,
+        ) -> i64 {
+            self.insert_inner(token, shown, user, nonce, ttl, false)
+        }
+
+        /// Like [`TokenStorage::insert`], but first removes every token issued to the player in the same namespace
+        ///
+        /// Both happen under one lock, so of two joins at the same time only one token stays valid.  
+        pub fn replace(
+            &self,
+            token: TokenKey,
+            shown: String,
+            user: Player,
+            nonce: Option<String>,
+            ttl: Option<Duration>,
+        ) -> i64 {
+            self.insert_inner(token, shown, user, nonce, ttl, true)
+        }
+
+        fn insert_inner(
+            &self,
+            token: TokenKey,
+            shown: String,
+            user: Player,
+            nonce: Option<String>,
+            ttl: Option<Duration>,
+            replace: bool,
+        ) -> i64 {
             let time = Utc::now();
+            let cell = StorageCell {
+                time,
+                // a ttl too long for a date never expires
+                expires: TimeDelta::from_std(ttl.unwrap_or(self.ttl))
+                    .ok()
+                    .and_then(|ttl| time.checked_add_signed(ttl))
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC),
+                data: user,
+                shown,
+                nonce,
+            };
 
-            lock.insert(token, StorageCell { time, data: user });
+            {
+                let mut lock = self.lock();
+                if replace {
+                    let uuid = cell.data.uuid;
+                    lock.tokens
+                        .retain(|(ns, _), c| *ns != token.0 || c.data.uuid != uuid);
+                    for ((ns, _), held) in lock.held.iter_mut() {
+                        if *ns == token.0 && held.uuid == uuid {
+                            held.revoked = true;
+                        }
+                    }
+                }
+                lock.push(token, cell);
+            }
+            // the new token might expire before whatever the cleaner is waiting on
+            self.tokens.1.notify_all();
 
             time.timestamp()
         }
 
-        pub fn get(&self, token: &String) -> Option<Player> {
-            self.tokens
+        pub fn get(&self, token: &TokenKey, nonce: Option<&str>) -> Option<Player> {
+            let mut expired = None;
+            let player = {
+                let mut lock = self.lock();
+
+                // a mismatched nonce doesn't consume the token,
+                // otherwise anyone knowing a token could invalidate it
+                match lock.get_valid(token, &mut expired) {
+                    Some(cell) if cell.matches_nonce(nonce) => lock.tokens.remove(token),
+                    _ => None,
+                }
+            };
+
+            self.send_expired(expired);
+            player.map(|s| s.data)
+        }
+
+        /// Returns the player behind a token without consuming it
+        pub fn peek(&self, token: &TokenKey) -> Option<Player> {
+            let mut expired = None;
+            let player = self
                 .lock()
-                .unwrap_or_else(|e| e.into_inner())
-                .remove(token)
-                .map(|s| s.data)
+                .get_valid(token, &mut expired)
+                .map(|s| s.data.clone());
+
+            self.send_expired(expired);
+            player
+        }
+
+        /// Takes a token out of storage until the returned [`Claim`] is committed or rolled back
+        pub fn claim(&self, token: TokenKey, nonce: Option<&str>) -> Option<Claim> {
+            let mut expired = None;
+            let claimed = {
+                let mut lock = self.lock();
+                let cell = match lock.get_valid(&token, &mut expired) {
+                    Some(cell) if cell.matches_nonce(nonce) => lock.tokens.remove(&token),
+                    _ => None,
+                };
+
+                cell.map(|cell| {
+                    let claim = lock.next_claim;
+                    lock.next_claim += 1;
+                    let held = Held {
+                        claim,
+                        uuid: cell.data.uuid,
+                        revoked: false,
+                    };
+                    lock.held.insert(token.clone(), held);
+                    (claim, cell)
+                })
+            };
+
+            self.send_expired(expired);
+            let (claim, cell) = claimed?;
+            Some(Claim {
+                token,
+                cell,
+                claim,
+                tokens: self.tokens.clone(),
+                done: false,
+            })
+        }
+
+        /// Removes a token, returns `true` if the token existed
+        ///
+        /// A claimed token is revoked too, it isn't put back when its claim is rolled back.  
+        pub fn revoke(&self, token: &TokenKey) -> bool {
+            let mut expired = None;
+            let revoked = {
+                let mut lock = self.lock();
+                let held = lock.held.get_mut(token).map(|held| {
+                    let was_revoked = held.revoked;
+                    held.revoked = true;
+                    !was_revoked
+                });
+
+                match held {
+                    Some(revoked) => revoked,
+                    None => {
+                        lock.get_valid(token, &mut expired).is_some()
+                            && lock.tokens.remove(token).is_some()
+                    }
+                }
+            };
+
+            self.send_expired(expired);
+            revoked
+        }
+
+        /// Returns the newest valid token issued to a player in a namespace, as it was shown
+        pub fn token_for(&self, namespace: Option<&str>, uuid: &Uuid) -> Option<String> {
+            self.latest_for(namespace, uuid, |_| true)
+        }
+
+        /// Returns the newest valid token issued to a player that is bound to the same nonce
+        pub fn reusable_token(
+            &self,
+            namespace: Option<&str>,
+            uuid: &Uuid,
+            nonce: Option<&str>,
+        ) -> Option<String> {
+            self.latest_for(namespace, uuid, |c| c.nonce.as_deref() == nonce)
+        }
+
+        fn latest_for(
+            &self,
+            namespace: Option<&str>,
+            uuid: &Uuid,
+            filter: impl Fn(&StorageCell) -> bool,
+        ) -> Option<String> {
+            let now = Utc::now();
+            self.lock()
+                .tokens
+                .iter()
+                .filter(|((ns, _), c)| {
+                    ns.as_deref() == namespace
+                        && c.data.uuid == *uuid
+                        && c.expires > now
+                        && filter(c)
+                })
+                .max_by_key(|(_, c)| c.time)
+                .map(|(_, c)| c.shown.clone())
+        }
+
+        /// Stops the cleaner thread and waits for it to return
+        pub fn shutdown(&self) {
+            self.lock().running = false;
+            self.tokens.1.notify_all();
+
+            if let Some(handle) = self.cleaner.lock().unwrap().take() {
+                let _ = handle.join();
+            }
+        }
+
+        fn send_expired(&self, expired: Option<StorageCell>) {
+            if let Some(cell) = expired {
+                Self::broadcast_expired(&self.broadcast, cell);
+            }
         }
 
-        fn start_storage_cleaner(tokens: StorageInternal, ttl: Duration) -> ! {
-            loop {
+        fn broadcast_expired(broadcast: &Broadcast, cell: StorageCell) {
+            broadcast.send(ChannelMessage::new(MessageData::OnExpire {
+                player: Box::new(cell.data),
+                token: cell.shown,
+            }));
+        }
+
+        /// Removes tokens as they expire, sleeping until the next token is due
+        fn start_storage_cleaner(tokens: StorageInternal, broadcast: Broadcast) {
+            let (lock, cvar) = &*tokens;
+            let mut inner = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+            while inner.running {
+                let now = Utc::now();
+
+                let mut expired = Vec::new();
+                while inner
+                    .expiries
+                    .peek()
+                    .is_some_and(|Reverse((time, _))| *time <= now)
                 {
-                    let mut lock = tokens.lock().unwrap_or_else(|e| e.into_inner());
+                    let Some(Reverse((time, token))) = inner.expiries.pop() else {
+                        break;
+                    };
 
-                    let mut invalid_tokens = Vec::new();
-                    for (token, data) in lock.iter() {
-                        if Utc::now() > (data.time + ttl) {
-                            invalid_tokens.push(token.clone());
-                        }
+                    // the token might have been verified already, or re-inserted with a new expiry
+                    if inner.tokens.get(&token).is_some_and(|c| c.expires == time)
+                        && let Some(cell) = inner.tokens.remove(&token)
+                    {
+                        expired.push(cell);
                     }
+                }
 
-                    for token in invalid_tokens {
-                        lock.remove(&token);
+                // don't hold the lock while broadcasting, subscribers might be slow
+                if !expired.is_empty() {
+                    drop(inner);
+                    for cell in expired {
+                        Self::broadcast_expired(&broadcast, cell);
                     }
+                    inner = lock.lock().unwrap_or_else(|e| e.into_inner());
+                    continue;
                 }
 
-                // we can also use the ttl for the interval
-                thread::sleep(ttl);
+                let wait = match inner.expiries.peek() {
+                    Some(Reverse((time, _))) => (*time - now).to_std().unwrap_or_default(),
+                    None => Self::IDLE_INTERVAL,
+                };
+
+                inner = cvar
+                    .wait_timeout(inner, wait)
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0;
             }
         }
     }
+
+    /// A token that has been taken out of storage, but not yet consumed
+    ///
+    /// While a claim is held the token can't be verified, peeked or claimed again.  
+    ///
+    /// Use [`Claim::commit`] once whatever depends on the player has succeeded,  
+    /// or [`Claim::rollback`] to make the token valid again.  
+    /// Dropping a claim without committing it also rolls it back.  
+    #[derive(Debug)]
+    pub struct Claim {
+        token: TokenKey,
+        cell: StorageCell,
+        /// Which claim of the token this is, see [`StorageInner::held`]
+        claim: u64,
+        tokens: StorageInternal,
+        done: bool,
+    }
+
+    impl Claim {
+        /// The player associated with the claimed token
+        pub fn player(&self) -> &Player {
+            &self.cell.data
+        }
+
+        /// The token that was claimed, normalized
+        pub fn token(&self) -> &str {
+            &self.token.1
+        }
+
+        /// Consumes the token for good, it can never be used again
+        pub fn commit(mut self) -> Player {
+            self.done = true;
+            self.cell.data.clone()
+        }
+
+        /// Puts the token back into storage with its original expiry
+        pub fn rollback(self) {
+            // putting the token back is handled by drop
+            drop(self);
+        }
+    }
+
+    impl Drop for Claim {
+        fn drop(&mut self) {
+            let mut lock = self.tokens.0.lock().unwrap_or_else(|e| e.into_inner());
+            let revoked = match lock.held.remove(&self.token) {
+                Some(held) if held.claim == self.claim => held.revoked,
+                // the token was issued and claimed again, that claim decides what happens to it
+                Some(held) => {
+                    lock.held.insert(self.token.clone(), held);
+                    true
+                }
+                None => true,
+            };
+
+            if !self.done && !revoked && !lock.tokens.contains_key(&self.token) {
+                lock.push(self.token.clone(), self.cell.clone());
+                self.tokens.1.notify_all();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::{storage::TokenStorage, *};
+    use crate::broadcast::Broadcast;
+
+    fn player() -> Player {
+        Player {
+            uuid: Uuid::nil(),
+            username: "Notch".to_string(),
+            vhost: None,
+            xuid: None,
+        }
+    }
+
+    #[test]
+    fn alphabet_rejects_empty_tokens() {
+        assert!(matches!(
+            AlphabetToken::new("", 8),
+            Err(MCHAError::EmptyToken(_))
+        ));
+        assert!(matches!(
+            AlphabetToken::unambiguous(0),
+            Err(MCHAError::EmptyToken(_))
+        ));
+    }
+
+    #[test]
+    fn alphabet_generate() {
+        let generator = AlphabetToken::new("ab", 12).unwrap();
+        let token = TokenGenerator::generate(&generator, &player());
+
+        assert_eq!(token.chars().count(), 12);
+        assert!(token.chars().all(|c| c == 'a' || c == 'b'));
+    }
+
+    #[test]
+    fn alphabet_display_and_normalize() {
+        let generator = AlphabetToken::default();
+
+        assert_eq!(TokenGenerator::display(&generator, "ABCDEFGH"), "ABCD-EFGH");
+        assert_eq!(
+            TokenGenerator::normalize(&generator, " abcd-efgh "),
+            "ABCDEFGH"
+        );
+    }
+
+    #[test]
+    fn numeric_and_word_reject_empty_tokens() {
+        assert!(matches!(
+            NumericToken::new(0),
+            Err(MCHAError::EmptyToken(_))
+        ));
+        assert!(matches!(WordToken::new(0), Err(MCHAError::EmptyToken(_))));
+    }
+
+    #[test]
+    fn numeric_generate() {
+        let generator = NumericToken::new(4).unwrap();
+        let token = TokenGenerator::generate(&generator, &player());
+
+        assert_eq!(token.len(), 4);
+        assert!(token.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn word_generate() {
+        let generator = WordToken::default();
+        let token = TokenGenerator::generate(&generator, &player());
+
+        let words: Vec<_> = token.split('-').collect();
+        assert_eq!(words.len(), 3);
+        assert!(words.iter().all(|word| WORDS.contains(word)));
+    }
+
+    #[test]
+    fn replace_keeps_other_players_and_namespaces() {
+        let storage = TokenStorage::new(Duration::from_secs(60), Broadcast::new());
+        let other = Player {
+            uuid: Uuid::from_u128(1),
+            ..player()
+        };
+        let key = |ns: Option<&str>, token: &str| (ns.map(str::to_string), token.to_string());
+
+        storage.insert(key(None, "A"), "A".into(), player(), None, None);
+        storage.insert(key(None, "B"), "B".into(), other, None, None);
+        storage.insert(key(Some("vhost"), "C"), "C".into(), player(), None, None);
+        storage.replace(key(None, "D"), "D".into(), player(), None, None);
+
+        assert!(storage.peek(&key(None, "A")).is_none());
+        assert!(storage.peek(&key(None, "B")).is_some());
+        assert!(storage.peek(&key(Some("vhost"), "C")).is_some());
+        assert!(storage.peek(&key(None, "D")).is_some());
+        storage.shutdown();
+    }
+
+    #[test]
+    fn insert_max_ttl() {
+        let storage = TokenStorage::new(Duration::from_secs(60), Broadcast::new());
+        let key = (None, "A".to_string());
+
+        storage.insert(key.clone(), "A".into(), player(), None, Some(Duration::MAX));
+
+        assert!(storage.peek(&key).is_some());
+        storage.shutdown();
+    }
+
+    #[test]
+    fn claim_rollback() {
+        let storage = TokenStorage::new(Duration::from_secs(60), Broadcast::new());
+        let key = (None, "A".to_string());
+        storage.insert(key.clone(), "A".into(), player(), None, None);
+
+        let claim = storage.claim(key.clone(), None).unwrap();
+        assert!(storage.peek(&key).is_none());
+        claim.rollback();
+        assert!(storage.peek(&key).is_some());
+
+        storage.claim(key.clone(), None).unwrap().commit();
+        assert!(storage.peek(&key).is_none());
+        storage.shutdown();
+    }
+
+    #[test]
+    fn revoke_during_claim() {
+        let storage = TokenStorage::new(Duration::from_secs(60), Broadcast::new());
+        let key = (None, "A".to_string());
+        storage.insert(key.clone(), "A".into(), player(), None, None);
+
+        let claim = storage.claim(key.clone(), None).unwrap();
+        assert!(storage.revoke(&key));
+        assert!(!storage.revoke(&key));
+        claim.rollback();
+
+        assert!(storage.peek(&key).is_none());
+        storage.shutdown();
+    }
+
+    #[test]
+    fn replace_during_claim() {
+        let storage = TokenStorage::new(Duration::from_secs(60), Broadcast::new());
+        let old = (None, "A".to_string());
+        let new = (None, "B".to_string());
+        storage.insert(old.clone(), "A".into(), player(), None, None);
+
+        let claim = storage.claim(old.clone(), None).unwrap();
+        storage.replace(new.clone(), "B".into(), player(), None, None);
+        claim.rollback();
+
+        assert!(storage.peek(&old).is_none());
+        assert!(storage.peek(&new).is_some());
+        storage.shutdown();
+    }
+
+    #[test]
+    fn concurrent_replace() {
+        let storage = TokenStorage::new(Duration::from_secs(60), Broadcast::new());
+
+        let joins: Vec<_> = (0..16)
+            .map(|i| {
+                let storage = storage.clone();
+                std::thread::spawn(move || {
+                    let token = i.to_string();
+                    storage.replace((None, token.clone()), token, player(), None, None);
+                })
+            })
+            .collect();
+        joins.into_iter().for_each(|join| join.join().unwrap());
+
+        let valid = (0..16)
+            .filter(|i| storage.peek(&(None, i.to_string())).is_some())
+            .count();
+        assert_eq!(valid, 1);
+        storage.shutdown();
+    }
 }